@@ -0,0 +1,7 @@
+// A known build environment for the crate's own tests of `build_info`, which reads
+// these at compile time in the instrumented crate. They only apply to this package,
+// so crates using the macros keep reading their own.
+fn main() {
+    println!("cargo::rustc-env=VERGEN_GIT_SHA=9c1e0d7a2b4f6e8d0c1a3b5d7f9e1c2a4b6d8f0e");
+    println!("cargo::rustc-env=VERGEN_BUILD_TIMESTAMP=2026-02-03T04:05:06Z");
+}
@@ -8,27 +8,329 @@ use syn::{
     ext::IdentExt,
     parse::{Parse, ParseStream},
     parse_macro_input,
-    spanned::Spanned,
 };
 
+/// How a custom field value is recorded on the span.
+enum FieldValue {
+    /// Record the value using its `SpanAttributeValue` impl, or `Debug` otherwise.
+    Debug(Expr),
+    /// Record a numeric value alongside a `<name>.unit` attribute.
+    Unit { value: Expr, unit: &'static str },
+    /// Record a stable, non-reversible hash of the value's `Debug` representation.
+    Hash(Expr),
+    /// Record the `Ok` value of a fallible expression, or a placeholder on `Err`.
+    Try(Expr),
+    /// Call a closure for the value, only if the span is recording.
+    Lazy(Expr),
+    /// Record the value's `serde_json` serialization.
+    Json(Expr),
+    /// Record the value as a string capped at a number of characters.
+    Truncate { value: Expr, len: Expr },
+    /// Record each entry of a map-like value as a `<name>.<key>` attribute, up to
+    /// an optional number of entries.
+    Spread { value: Expr, max: Option<Expr> },
+}
+
+impl FieldValue {
+    /// Check if the value refers to the `outcome` of the call as a bare variable, which
+    /// makes it a post-call field, recorded once the function has returned.
+    fn uses_outcome(&self) -> bool {
+        let tokens = match self {
+            FieldValue::Debug(expr)
+            | FieldValue::Hash(expr)
+            | FieldValue::Try(expr)
+            | FieldValue::Lazy(expr)
+            | FieldValue::Json(expr) => quote!(#expr),
+            FieldValue::Unit { value, .. } => quote!(#value),
+            FieldValue::Truncate { value, len } => quote!(#value #len),
+            FieldValue::Spread { value, max } => quote!(#value #max),
+        };
+        refers_to_variable(tokens, "outcome")
+    }
+
+    /// Recognize helper pseudo-functions like `bytes(len)`, `millis(elapsed)`,
+    /// `hash(user_id)`, `variant(event)`, `env("POD_NAME")`, `pretty(config)`,
+    /// `json(body)`, `spread(labels, 16)`, `fmt(format_ts, ts)` or `truncate(blob, 64)` and
+    /// argument-less closures, falling back to plain `Debug` formatting for any
+    /// other expression.
+    fn from_expr(expr: Expr) -> syn::Result<Self> {
+        if let Expr::Closure(closure) = &expr
+            && closure.inputs.is_empty()
+        {
+            return Ok(FieldValue::Lazy(expr));
+        }
+        if let Expr::Call(call) = &expr
+            && let Expr::Path(func) = call.func.as_ref()
+            && matches!(call.args.len(), 1 | 2)
+            && func.path.is_ident("spread")
+        {
+            return Ok(FieldValue::Spread {
+                value: call.args[0].clone(),
+                max: call.args.get(1).cloned(),
+            });
+        }
+        if let Expr::Call(call) = &expr
+            && let Expr::Path(func) = call.func.as_ref()
+            && call.args.len() == 2
+            && func.path.is_ident("fmt")
+        {
+            // The formatter decides the representation, recorded like any other value
+            let (formatter, value) = (&call.args[0], &call.args[1]);
+            return Ok(FieldValue::Debug(syn::parse_quote!((#formatter)(&(#value)))));
+        }
+        if let Expr::Call(call) = &expr
+            && let Expr::Path(func) = call.func.as_ref()
+            && call.args.len() == 2
+            && func.path.is_ident("truncate")
+        {
+            return Ok(FieldValue::Truncate {
+                value: call.args[0].clone(),
+                len: call.args[1].clone(),
+            });
+        }
+        if let Expr::Call(call) = &expr
+            && let Expr::Path(func) = call.func.as_ref()
+            && call.args.len() == 1
+        {
+            let arg = &call.args[0];
+            if func.path.is_ident("bytes") {
+                return Ok(FieldValue::Unit {
+                    value: syn::parse_quote!((#arg) as i64),
+                    unit: "By",
+                });
+            }
+            if func.path.is_ident("millis") {
+                return Ok(FieldValue::Unit {
+                    value: syn::parse_quote!((#arg).as_millis() as i64),
+                    unit: "ms",
+                });
+            }
+            if func.path.is_ident("hash") {
+                return Ok(FieldValue::Hash(arg.clone()));
+            }
+            if func.path.is_ident("env") {
+                // Read at call time, so changes to the environment are picked up
+                return Ok(FieldValue::Debug(syn::parse_quote!(
+                    ::std::env::var(#arg).unwrap_or_default()
+                )));
+            }
+            if func.path.is_ident("pretty") {
+                return Ok(FieldValue::Debug(syn::parse_quote!(format!("{:#?}", #arg))));
+            }
+            if func.path.is_ident("variant") {
+                // `variant_name` is generated by `#[derive(VariantName)]`
+                return Ok(FieldValue::Debug(syn::parse_quote!((#arg).variant_name())));
+            }
+            if func.path.is_ident("json") {
+                if cfg!(feature = "json") {
+                    return Ok(FieldValue::Json(arg.clone()));
+                }
+                return Err(syn::Error::new_spanned(
+                    func,
+                    "`json(...)` requires the `json` feature",
+                ));
+            }
+        }
+        Ok(FieldValue::Debug(expr))
+    }
+}
+
+/// Parse the comma separated `name = value` pairs of a `fields(...)` list.
+fn parse_fields(content: ParseStream) -> syn::Result<Vec<(String, FieldValue)>> {
+    let mut fields = Vec::new();
+    while !content.is_empty() {
+        let field_name: Ident = content.parse()?;
+        let field_value = if content.peek(Token![=]) {
+            content.parse::<Token![=]>()?;
+            // `try` is a reserved keyword, so it can't be parsed as a call
+            if content.peek(Token![try]) && content.peek2(syn::token::Paren) {
+                content.parse::<Token![try]>()?;
+                let inner;
+                syn::parenthesized!(inner in content);
+                FieldValue::Try(inner.parse()?)
+            } else {
+                FieldValue::from_expr(content.parse::<Expr>()?)?
+            }
+        } else {
+            // Fallback to name = name shorthand
+            FieldValue::Debug(syn::parse_quote!(#field_name))
+        };
+        fields.push((field_name.to_string(), field_value));
+        if !content.is_empty() {
+            content.parse::<Token![,]>()?;
+        }
+    }
+    Ok(fields)
+}
+
+/// Generate the statements recording a custom field on `__otel_span`.
+fn field_attr(name: &str, value: &FieldValue) -> proc_macro2::TokenStream {
+    match value {
+        FieldValue::Debug(expr) => {
+            let value = attribute_value(expr);
+            quote! {
+                __otel_span.set_attribute(::opentelemetry::KeyValue::new(#name, #value));
+            }
+        }
+        FieldValue::Unit { value, unit } => {
+            let unit_name = format!("{name}.unit");
+            quote! {
+                __otel_span.set_attribute(::opentelemetry::KeyValue::new(#name, #value));
+                __otel_span.set_attribute(::opentelemetry::KeyValue::new(#unit_name, #unit));
+            }
+        }
+        FieldValue::Try(expr) => {
+            let value = attribute_value(quote!(value));
+            quote! {
+                __otel_span.set_attribute(::opentelemetry::KeyValue::new(#name, match #expr {
+                    Ok(value) => #value,
+                    Err(_) => ::opentelemetry::Value::from("<err>"),
+                }));
+            }
+        }
+        FieldValue::Lazy(closure) => {
+            let value = attribute_value(quote!(value));
+            quote! {
+                if __otel_span.is_recording() {
+                    let value = (#closure)();
+                    __otel_span.set_attribute(::opentelemetry::KeyValue::new(#name, #value));
+                }
+            }
+        }
+        FieldValue::Truncate { value, len } => {
+            let value = attribute_value(value);
+            quote! {
+                __otel_span.set_attribute(::opentelemetry::KeyValue::new(
+                    #name,
+                    #value.as_str().chars().take(#len).collect::<String>(),
+                ));
+            }
+        }
+        FieldValue::Spread { value, max } => {
+            let entry_value = attribute_value(quote!(value));
+            let max = max.as_ref().map(|max| quote!(.take(#max)));
+            quote! {
+                // Method syntax auto-derefs, so references to maps work too
+                for (key, value) in (&(#value)).into_iter() #max {
+                    __otel_span.set_attribute(::opentelemetry::KeyValue::new(
+                        format!("{}.{}", #name, key),
+                        #entry_value,
+                    ));
+                }
+            }
+        }
+        FieldValue::Json(expr) => quote! {
+            __otel_span.set_attribute(::opentelemetry::KeyValue::new(
+                #name,
+                ::serde_json::to_string(&(#expr)).unwrap_or_default(),
+            ));
+        },
+        // 64-bit FNV-1a, which unlike std's hashers is guaranteed to be stable
+        FieldValue::Hash(expr) => quote! {
+            __otel_span.set_attribute(::opentelemetry::KeyValue::new(#name, {
+                let hash = format!("{:?}", #expr)
+                    .bytes()
+                    .fold(0xcbf29ce484222325u64, |hash, byte| {
+                        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+                    });
+                format!("{:016x}", hash)
+            }));
+        },
+    }
+}
+
+/// Error recording options, parsed from `err`, `err = <expr>` or `err(...)`.
+#[derive(Default)]
+struct ErrArgs {
+    /// Expression evaluating to the `&dyn Error` passed to `record_error`,
+    /// defaulting to the error itself.
+    record: Option<Expr>,
+    /// Expression deciding whether the error is retryable rather than fatal.
+    retryable: Option<Expr>,
+    /// Expression deciding whether the error is recorded, rather than treated as expected.
+    record_if: Option<Expr>,
+    /// Also emit a `log::error!` line for the error.
+    log: bool,
+    /// Skip the `error` attribute, leaving the error text to the exception event.
+    no_attr: bool,
+    /// Record the error's variant name as `error.variant`.
+    variant: bool,
+    /// Attributes derived from the error, recorded as `error.<name>`.
+    attrs: Vec<(String, Expr)>,
+}
+
 #[derive(Default)]
 struct InstrumentArgs {
     skip: HashSet<String>,
     skip_all: bool,
-    fields: Vec<(String, Expr)>,
+    fields: Vec<(String, FieldValue)>,
+    cfg_fields: Vec<(proc_macro2::TokenStream, Vec<(String, FieldValue)>)>,
     ret: bool,
-    err: Option<Expr>,
+    ret_projections: Vec<(String, Expr)>,
+    ret_variant: bool,
+    ret_display: bool,
+    ret_compact: Option<usize>,
+    ret_len: Option<Ident>,
+    err: Option<ErrArgs>,
     name: Option<String>,
+    name_expr: Option<Expr>,
     parent: Option<Expr>,
+    record_parent_id: bool,
+    task_local: bool,
+    manual_status: bool,
+    start_time: Option<Expr>,
+    package_info: bool,
+    request_id: Option<Expr>,
+    has_parent: bool,
+    slo_ms: Option<Expr>,
+    latency_buckets: Option<Vec<u64>>,
+    gate: Option<Expr>,
+    min_duration_ms: Option<Expr>,
+    stream: bool,
+    expose_ids: Option<Expr>,
+    build_info: bool,
+    record_async: bool,
+    record_visibility: bool,
+    qualified_name: bool,
+    record_ok: bool,
+    root: bool,
+    root_provider: Option<Expr>,
+    namespace_attrs: bool,
+    task_id: bool,
+    param_names: bool,
+    context_scoped: Option<Ident>,
+    metric_only: bool,
+    baggage_attrs: Vec<String>,
+    tracer: Option<Ident>,
+    test_ids: bool,
+    strict: bool,
+    /// The options that were given, so options unsupported in a mode can be reported
+    /// on their own tokens.
+    options: Vec<Ident>,
+    /// Legacy forms that were used, with the note pointing to their replacement, and
+    /// other notes warning about options that have no effect.
+    deprecations: Vec<(proc_macro2::Span, &'static str)>,
 }
 
 impl Parse for InstrumentArgs {
     fn parse(input: ParseStream) -> syn::Result<Self> {
         let mut args = InstrumentArgs::default();
+        let mut seen = HashSet::new();
 
         while !input.is_empty() {
             let ident: Ident = input.parse()?;
-            match ident.to_string().as_str() {
+            let key = ident.to_string();
+            // Lists accumulate, while giving any other option twice is likely a mistake
+            if !matches!(key.as_str(), "skip" | "fields" | "cfg" | "baggage_attrs")
+                && !seen.insert(key.clone())
+            {
+                return Err(syn::Error::new_spanned(
+                    ident,
+                    format!("Duplicate `{key}` argument"),
+                ));
+            }
+            args.options.push(ident.clone());
+            match key.as_str() {
                 "skip_all" => {
                     args.skip_all = true;
                 }
@@ -36,48 +338,331 @@ impl Parse for InstrumentArgs {
                     let content;
                     syn::parenthesized!(content in input);
                     let names = content.parse_terminated(Ident::parse_any, Token![,])?;
-                    args.skip = names.into_iter().map(|i| i.to_string()).collect();
+                    args.skip.extend(names.into_iter().map(|i| i.to_string()));
                 }
                 "fields" => {
                     let content;
                     syn::parenthesized!(content in input);
+                    args.fields.extend(parse_fields(&content)?);
+                }
+                "cfg" => {
+                    // `cfg(<predicate>)(fields(...))` records fields only when the
+                    // predicate holds in the instrumented crate
+                    let predicate;
+                    syn::parenthesized!(predicate in input);
+                    let predicate: proc_macro2::TokenStream = predicate.parse()?;
+                    let content;
+                    syn::parenthesized!(content in input);
+                    let mut fields = Vec::new();
                     while !content.is_empty() {
-                        let field_name: Ident = content.parse()?;
-                        let field_expr = if content.peek(Token![=]) {
-                            content.parse::<Token![=]>()?;
-                            content.parse::<Expr>()?
-                        } else {
-                            // Fallback to name = name shorthand
-                            syn::parse_quote!(#field_name)
-                        };
-                        args.fields.push((field_name.to_string(), field_expr));
+                        let ident: Ident = content.parse()?;
+                        if ident != "fields" {
+                            return Err(syn::Error::new_spanned(
+                                ident,
+                                "Only `fields` can be guarded by `cfg`",
+                            ));
+                        }
+                        let inner;
+                        syn::parenthesized!(inner in content);
+                        fields.extend(parse_fields(&inner)?);
                         if !content.is_empty() {
                             content.parse::<Token![,]>()?;
                         }
                     }
+                    args.cfg_fields.push((predicate, fields));
                 }
                 "ret" => {
                     args.ret = true;
+                    if input.peek(syn::token::Paren) {
+                        let content;
+                        syn::parenthesized!(content in input);
+                        while !content.is_empty() {
+                            let key: Ident = content.parse()?;
+                            // `ret(variant)` records only the variant name of an enum,
+                            // and `ret(Display)` formats the value using `Display`
+                            if (key == "variant" || key == "Display") && !content.peek(Token![=]) {
+                                if key == "variant" {
+                                    args.ret_variant = true;
+                                } else {
+                                    args.ret_display = true;
+                                }
+                                if !content.is_empty() {
+                                    content.parse::<Token![,]>()?;
+                                }
+                                continue;
+                            }
+                            // `ret(len)` records the length of a collection
+                            if key == "len" && !content.peek(Token![=]) {
+                                args.ret_len = Some(key);
+                                if !content.is_empty() {
+                                    content.parse::<Token![,]>()?;
+                                }
+                                continue;
+                            }
+                            // `ret(compact)` records the outcome, and the value only if
+                            // it is short, optionally with a `compact = <limit>`
+                            if key == "compact" && !content.peek2(Token![.]) {
+                                let mut limit = 64;
+                                if content.peek(Token![=]) {
+                                    content.parse::<Token![=]>()?;
+                                    limit = content.parse::<syn::LitInt>()?.base10_parse()?;
+                                }
+                                args.ret_compact = Some(limit);
+                                if !content.is_empty() {
+                                    content.parse::<Token![,]>()?;
+                                }
+                                continue;
+                            }
+                            content.parse::<Token![=]>()?;
+                            // Collect the projection up to the next comma, and apply it
+                            // to the success value, like `.0.len()` or `.items[0]`
+                            let mut projection = proc_macro2::TokenStream::new();
+                            while !content.is_empty() && !content.peek(Token![,]) {
+                                projection.extend([content.parse::<proc_macro2::TokenTree>()?]);
+                            }
+                            if projection.is_empty() {
+                                return Err(syn::Error::new_spanned(key, "Expected a projection"));
+                            }
+                            let expr: Expr = syn::parse2(quote!(__otel_ret #projection))?;
+                            args.ret_projections.push((key.to_string(), expr));
+                            if !content.is_empty() {
+                                content.parse::<Token![,]>()?;
+                            }
+                        }
+                        if args.ret_compact.is_some()
+                            && (args.ret_variant
+                                || args.ret_len.is_some()
+                                || !args.ret_projections.is_empty())
+                        {
+                            return Err(syn::Error::new_spanned(
+                                ident,
+                                "`ret(compact)` can't be combined with `variant`, `len` or projections",
+                            ));
+                        }
+                    }
                 }
                 "err" => {
+                    let mut err_args = ErrArgs::default();
                     if input.peek(Token![=]) {
                         input.parse::<Token![=]>()?;
-                        let err_expr: Expr = input.parse()?;
-                        args.err = Some(err_expr);
-                    } else {
-                        args.err = Some(syn::parse_quote!(e));
+                        err_args.record = Some(input.parse()?);
+                    } else if input.peek(syn::token::Paren) {
+                        let content;
+                        syn::parenthesized!(content in input);
+                        while !content.is_empty() {
+                            let option: Ident = content.parse()?;
+                            match option.to_string().as_str() {
+                                "record" => {
+                                    content.parse::<Token![=]>()?;
+                                    err_args.record = Some(content.parse()?);
+                                }
+                                "retryable" => {
+                                    content.parse::<Token![=]>()?;
+                                    err_args.retryable = Some(content.parse()?);
+                                }
+                                "record_if" => {
+                                    content.parse::<Token![=]>()?;
+                                    err_args.record_if = Some(content.parse()?);
+                                }
+                                "no_attr" => {
+                                    err_args.no_attr = true;
+                                }
+                                "variant" => {
+                                    err_args.variant = true;
+                                }
+                                // Any other `name = <expr>` records an attribute derived from the error
+                                name if content.peek(Token![=]) => {
+                                    content.parse::<Token![=]>()?;
+                                    err_args.attrs.push((format!("error.{name}"), content.parse()?));
+                                }
+                                #[cfg(feature = "log")]
+                                "log" => {
+                                    err_args.log = true;
+                                }
+                                #[cfg(not(feature = "log"))]
+                                "log" => {
+                                    return Err(syn::Error::new_spanned(
+                                        option,
+                                        "`err(log)` requires the `log` feature",
+                                    ));
+                                }
+                                _ => {
+                                    return Err(syn::Error::new_spanned(
+                                        option,
+                                        "Unknown err option",
+                                    ));
+                                }
+                            }
+                            if !content.is_empty() {
+                                content.parse::<Token![,]>()?;
+                            }
+                        }
                     }
+                    args.err = Some(err_args);
                 }
                 "name" => {
                     input.parse::<Token![=]>()?;
-                    let name_str: syn::LitStr = input.parse()?;
-                    args.name = Some(name_str.value());
+                    // Literal names are known at compile time, while any other
+                    // expression is evaluated when the function is called
+                    match input.parse::<Expr>()? {
+                        // An empty name falls back to the function name
+                        Expr::Lit(syn::ExprLit {
+                            lit: syn::Lit::Str(name_str),
+                            ..
+                        }) => args.name = Some(name_str.value()).filter(|name| !name.is_empty()),
+                        name_expr => args.name_expr = Some(name_expr),
+                    }
                 }
                 "parent" => {
                     input.parse::<Token![=]>()?;
                     let parent_expr: Expr = input.parse()?;
                     args.parent = Some(parent_expr);
                 }
+                "start_time" => {
+                    input.parse::<Token![=]>()?;
+                    args.start_time = Some(input.parse()?);
+                }
+                "package_info" => {
+                    args.package_info = true;
+                }
+                "request_id" => {
+                    input.parse::<Token![=]>()?;
+                    args.request_id = Some(input.parse()?);
+                }
+                "expose_ids" => {
+                    input.parse::<Token![=]>()?;
+                    args.expose_ids = Some(input.parse()?);
+                }
+                "stream" => {
+                    args.stream = true;
+                }
+                "slo_ms" => {
+                    input.parse::<Token![=]>()?;
+                    args.slo_ms = Some(input.parse()?);
+                }
+                "gate" => {
+                    input.parse::<Token![=]>()?;
+                    args.gate = Some(input.parse()?);
+                }
+                "latency_bucket" => {
+                    // Upper bounds in milliseconds, defaulting to orders of magnitude
+                    let mut bounds = vec![10, 100, 1000];
+                    if input.peek(syn::token::Paren) {
+                        let content;
+                        syn::parenthesized!(content in input);
+                        let lits = content.parse_terminated(syn::LitInt::parse, Token![,])?;
+                        bounds = lits
+                            .iter()
+                            .map(syn::LitInt::base10_parse)
+                            .collect::<syn::Result<_>>()?;
+                        if bounds.is_empty() || bounds.windows(2).any(|pair| pair[0] >= pair[1]) {
+                            return Err(syn::Error::new_spanned(
+                                lits,
+                                "`latency_bucket` boundaries must be given in increasing order",
+                            ));
+                        }
+                    }
+                    args.latency_buckets = Some(bounds);
+                }
+                "min_duration_ms" => {
+                    input.parse::<Token![=]>()?;
+                    args.min_duration_ms = Some(input.parse()?);
+                }
+                "build_info" => {
+                    args.build_info = true;
+                }
+                "has_parent" => {
+                    args.has_parent = true;
+                }
+                "record_ok" => {
+                    args.record_ok = true;
+                }
+                "root" => {
+                    args.root = true;
+                    if input.peek(Token![=]) {
+                        input.parse::<Token![=]>()?;
+                        args.root_provider = Some(input.parse()?);
+                    }
+                }
+                #[cfg(feature = "tokio")]
+                "task_id" => {
+                    args.task_id = true;
+                }
+                #[cfg(not(feature = "tokio"))]
+                "task_id" => {
+                    return Err(syn::Error::new_spanned(
+                        ident,
+                        "`task_id` requires the `tokio` feature",
+                    ));
+                }
+                "context_scoped" => {
+                    input.parse::<Token![=]>()?;
+                    args.context_scoped = Some(input.parse()?);
+                }
+                "baggage_attrs" => {
+                    let content;
+                    syn::parenthesized!(content in input);
+                    while !content.is_empty() {
+                        let mut key = content.call(Ident::parse_any)?.to_string();
+                        while content.peek(Token![.]) {
+                            content.parse::<Token![.]>()?;
+                            key.push('.');
+                            key.push_str(&content.call(Ident::parse_any)?.to_string());
+                        }
+                        args.baggage_attrs.push(key);
+                        if !content.is_empty() {
+                            content.parse::<Token![,]>()?;
+                        }
+                    }
+                }
+                "metric_only" => {
+                    args.metric_only = true;
+                }
+                "param_names" => {
+                    args.param_names = true;
+                }
+                "namespace_attrs" => {
+                    args.namespace_attrs = true;
+                }
+                "qualified_name" => {
+                    args.qualified_name = true;
+                }
+                "record_async" => {
+                    args.record_async = true;
+                }
+                "record_visibility" => {
+                    args.record_visibility = true;
+                }
+                "record_parent_id" => {
+                    args.record_parent_id = true;
+                }
+                "strict" => {
+                    args.strict = true;
+                }
+                "test_ids" => {
+                    args.test_ids = true;
+                }
+                "tracer" => {
+                    input.parse::<Token![=]>()?;
+                    args.tracer = Some(input.parse()?);
+                }
+                "task_local" => {
+                    args.task_local = true;
+                }
+                "status" => {
+                    input.parse::<Token![=]>()?;
+                    let mode: Ident = input.parse()?;
+                    match mode.to_string().as_str() {
+                        "auto" => args.manual_status = false,
+                        "manual" => args.manual_status = true,
+                        _ => {
+                            return Err(syn::Error::new_spanned(
+                                mode,
+                                "Unknown status mode, expected `auto` or `manual`",
+                            ));
+                        }
+                    }
+                }
                 _ => {
                     return Err(syn::Error::new_spanned(ident, "Unknown attribute"));
                 }
@@ -88,6 +673,124 @@ impl Parse for InstrumentArgs {
             }
         }
 
+        if args.root && (args.parent.is_some() || args.task_local) {
+            return Err(syn::Error::new(
+                input.span(),
+                "`root` can't be combined with `parent` or `task_local`",
+            ));
+        }
+
+        Ok(args)
+    }
+}
+
+/// Arguments of `tracer_name!`, parsed from `"name"` optionally followed by
+/// `resource(key = value, ...)`, `default_fields(key = value, ...)`,
+/// `build(commit = ..., time = ...)`, `schema_url = "..."` and named scopes like
+/// `db = "svc.db"`.
+struct TracerNameArgs {
+    name: String,
+    schema_url: Option<syn::LitStr>,
+    scopes: Vec<(Ident, syn::LitStr)>,
+    resource: Vec<(String, Expr)>,
+    default_fields: Vec<(String, Expr)>,
+    build_commit: Option<Expr>,
+    build_time: Option<Expr>,
+}
+
+/// Parse comma separated `key = value` pairs, where keys are dotted names like
+/// `deployment.environment`.
+fn parse_key_values(content: ParseStream) -> syn::Result<Vec<(String, Expr)>> {
+    let mut pairs = Vec::new();
+    while !content.is_empty() {
+        let mut key = content.call(Ident::parse_any)?.to_string();
+        while content.peek(Token![.]) {
+            content.parse::<Token![.]>()?;
+            key.push('.');
+            key.push_str(&content.call(Ident::parse_any)?.to_string());
+        }
+        content.parse::<Token![=]>()?;
+        pairs.push((key, content.parse()?));
+        if !content.is_empty() {
+            content.parse::<Token![,]>()?;
+        }
+    }
+    Ok(pairs)
+}
+
+impl Parse for TracerNameArgs {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let mut args = TracerNameArgs {
+            name: "otel-instrument".to_string(),
+            schema_url: None,
+            scopes: Vec::new(),
+            resource: Vec::new(),
+            default_fields: Vec::new(),
+            build_commit: None,
+            build_time: None,
+        };
+        if input.peek(syn::LitStr) {
+            args.name = input.parse::<syn::LitStr>()?.value();
+            if !input.is_empty() {
+                input.parse::<Token![,]>()?;
+            }
+        }
+
+        while !input.is_empty() {
+            let ident: Ident = input.parse()?;
+            if ident == "schema_url" {
+                input.parse::<Token![=]>()?;
+                args.schema_url = Some(input.parse()?);
+                if !input.is_empty() {
+                    input.parse::<Token![,]>()?;
+                }
+                continue;
+            }
+            // Any other `name = "..."` declares an additional scope
+            if input.peek(Token![=]) {
+                input.parse::<Token![=]>()?;
+                args.scopes.push((ident, input.parse()?));
+                if !input.is_empty() {
+                    input.parse::<Token![,]>()?;
+                }
+                continue;
+            }
+            let content;
+            syn::parenthesized!(content in input);
+            match ident.to_string().as_str() {
+                "resource" => args.resource.extend(parse_key_values(&content)?),
+                "default_fields" => args.default_fields.extend(parse_key_values(&content)?),
+                "build" => {
+                    while !content.is_empty() {
+                        let option: Ident = content.parse()?;
+                        content.parse::<Token![=]>()?;
+                        match option.to_string().as_str() {
+                            "commit" => args.build_commit = Some(content.parse()?),
+                            "time" => args.build_time = Some(content.parse()?),
+                            _ => {
+                                return Err(syn::Error::new_spanned(
+                                    option,
+                                    "Unknown build option",
+                                ));
+                            }
+                        }
+                        if !content.is_empty() {
+                            content.parse::<Token![,]>()?;
+                        }
+                    }
+                }
+                _ => {
+                    return Err(syn::Error::new_spanned(
+                        ident,
+                        "Unknown tracer_name option",
+                    ));
+                }
+            }
+            if !input.is_empty() {
+                input.parse::<Token![,]>()?;
+            }
+        }
+
         Ok(args)
     }
 }
@@ -95,251 +798,3010 @@ impl Parse for InstrumentArgs {
 /// Define the global tracer name for instrumentation.
 /// If not called, defaults to "otel-instrument".
 ///
+/// Also defines an `_otel` module holding the `SpanAttributeValue` trait, which can
+/// be implemented to control how types are recorded as span attributes, the
+/// `Traceable` marker for types recorded by `#[instrument(strict)]`, the
+/// `ParentContext` trait for types accepted by `parent = ...`, and the
+/// `traceparent` helper parsing W3C `traceparent` header values. Instrumented
+/// functions in submodules need `_OTEL_TRACER_NAME` in scope, like with
+/// `use crate::_OTEL_TRACER_NAME;`.
+///
 /// # Example
 /// ```rust
 /// use otel_instrument::tracer_name;
 ///
 /// tracer_name!("my-service");
 /// ```
+///
+/// Components of a crate can report under their own instrumentation scopes, declared
+/// as `name = "scope"` and selected with `#[instrument(tracer = name)]`:
+/// ```rust
+/// use otel_instrument::{instrument, tracer_name};
+///
+/// tracer_name!("my-service", db = "my-service.db", http = "my-service.http");
+///
+/// #[instrument(tracer = db)]
+/// fn query(sql: &str) -> Result<(), String> {
+///     Ok(())
+/// }
+/// ```
+///
+/// The schema URL of the semantic conventions the spans follow can be set with
+/// `schema_url = "..."`, and is exported with the instrumentation scope:
+/// ```rust
+/// use otel_instrument::tracer_name;
+///
+/// tracer_name!("my-service", schema_url = "https://opentelemetry.io/schemas/1.25.0");
+/// ```
+///
+/// Constant attributes that describe the whole service can be declared with
+/// `resource(...)`. They are returned by the generated `_otel::resource_attributes()`
+/// function, to be passed to the tracer provider's resource:
+/// ```rust
+/// use otel_instrument::tracer_name;
+///
+/// tracer_name!("my-service", resource(deployment.environment = "prod"));
+///
+/// let resource = opentelemetry_sdk::Resource::builder()
+///     .with_attributes(_otel::resource_attributes())
+///     .build();
+/// ```
 #[proc_macro]
 pub fn tracer_name(input: TokenStream) -> TokenStream {
-    let tracer_name = if input.is_empty() {
-        "otel-instrument".to_string()
+    let TracerNameArgs {
+        name: tracer_name,
+        schema_url,
+        scopes,
+        resource,
+        default_fields,
+        build_commit,
+        build_time,
+    } = parse_macro_input!(input as TracerNameArgs);
+    let default_fields_len = default_fields.len();
+    let default_fields = default_fields
+        .iter()
+        .map(|(key, value)| quote! { ::opentelemetry::KeyValue::new(#key, #value) });
+    // `option_env!` expands in the instrumented crate, so these read its build environment
+    let build_commit = build_commit.map_or_else(
+        || quote!(option_env!("VERGEN_GIT_SHA")),
+        |commit| quote!(Some(#commit)),
+    );
+    let build_time = build_time.map_or_else(
+        || quote!(option_env!("VERGEN_BUILD_TIMESTAMP")),
+        |time| quote!(Some(#time)),
+    );
+    let schema_url = schema_url.map(|url| quote!(.with_schema_url(#url)));
+    // Cache the tracer of every scope if enabled, so `#[instrument]` only looks it
+    // up from the global provider on the first call
+    let cached_tracers = if cfg!(feature = "cached_tracer") {
+        let suffixes = std::iter::once(String::new())
+            .chain(scopes.iter().map(|(ident, _)| format!("_{ident}")));
+        let cached = suffixes.map(|suffix| {
+            let scope_fn = quote::format_ident!("_otel_scope{}", suffix);
+            let tracer_fn = quote::format_ident!("_otel_tracer{}", suffix);
+            quote! {
+                /// The tracer of the instrumentation scope, looked up once.
+                #[doc(hidden)]
+                #[allow(dead_code)]
+                pub(crate) fn #tracer_fn() -> &'static ::opentelemetry::global::BoxedTracer {
+                    static TRACER: ::std::sync::OnceLock<::opentelemetry::global::BoxedTracer> =
+                        ::std::sync::OnceLock::new();
+                    TRACER.get_or_init(|| ::opentelemetry::global::tracer_with_scope(#scope_fn()))
+                }
+            }
+        });
+        quote!(#(#cached)*)
     } else {
-        let literal: syn::LitStr = parse_macro_input!(input as syn::LitStr);
-        literal.value()
+        proc_macro2::TokenStream::new()
     };
+    let scopes = scopes.iter().map(|(ident, name)| {
+        let scope_fn = quote::format_ident!("_otel_scope_{}", ident);
+        quote! {
+            /// A named instrumentation scope, selected with `#[instrument(tracer = ...)]`.
+            #[doc(hidden)]
+            #[allow(dead_code)]
+            pub(crate) fn #scope_fn() -> ::opentelemetry::InstrumentationScope {
+                ::opentelemetry::InstrumentationScope::builder(#name)
+                    #schema_url
+                    .build()
+            }
+        }
+    });
+    let resource_attrs = resource
+        .iter()
+        .map(|(key, value)| quote! { ::opentelemetry::KeyValue::new(#key, #value) });
+
+    // Primitives that convert losslessly into the typed OpenTelemetry values
+    let int_types = ["i8", "i16", "i32", "i64", "u8", "u16", "u32"]
+        .map(|ty| Ident::new(ty, proc_macro2::Span::call_site()));
+    let float_types = ["f32", "f64"].map(|ty| Ident::new(ty, proc_macro2::Span::call_site()));
+    let traceable_types = [
+        "i8", "i16", "i32", "i64", "i128", "isize", "u8", "u16", "u32", "u64", "u128", "usize",
+        "f32", "f64",
+    ]
+    .map(|ty| Ident::new(ty, proc_macro2::Span::call_site()));
 
     let expanded = quote! {
         pub(crate) const _OTEL_TRACER_NAME: &str = #tracer_name;
-    };
 
-    expanded.into()
-}
+        /// Items used by `#[instrument]`, in a module sharing the name of the constant,
+        /// so importing `_OTEL_TRACER_NAME` into a submodule brings both into scope.
+        /// Values given to `tracer_name!` are evaluated with the items of the calling
+        /// module in scope.
+        #[doc(hidden)]
+        #[allow(non_snake_case, unused_imports)]
+        pub(crate) mod _OTEL_TRACER_NAME {
+            use super::*;
 
-/// See crate level documentation for usage.
-#[proc_macro_attribute]
-pub fn instrument(args: TokenStream, input: TokenStream) -> TokenStream {
-    let input_fn = parse_macro_input!(input as ItemFn);
-    let args = if args.is_empty() {
-        InstrumentArgs::default()
-    } else {
-        parse_macro_input!(args as InstrumentArgs)
-    };
+            /// The instrumentation scope of the tracer used by `#[instrument]`.
+            #[doc(hidden)]
+            pub(crate) fn _otel_scope() -> ::opentelemetry::InstrumentationScope {
+                ::opentelemetry::InstrumentationScope::builder(#tracer_name)
+                    #schema_url
+                    .build()
+            }
 
-    match instrument_impl(args, input_fn) {
-        Ok(tokens) => tokens.into(),
-        Err(err) => err.to_compile_error().into(),
-    }
-}
+            #(#scopes)*
 
-fn extract_ident_from_pattern(pat: &syn::Pat) -> Option<Ident> {
-    match pat {
-        syn::Pat::Ident(ident) => Some(ident.ident.clone()),
-        syn::Pat::TupleStruct(tuple_struct) => {
-            // Handle patterns like State(state): State<AppState>
-            // Extract the first inner pattern if it's an identifier
-            if let Some(first_pattern) = tuple_struct.elems.first() {
-                extract_ident_from_pattern(first_pattern)
-            } else {
-                None
+            #cached_tracers
+
+            /// Attributes declared with `tracer_name!(..., default_fields(...))`, recorded
+            /// on every span created by `#[instrument]`.
+            #[doc(hidden)]
+            pub(crate) fn _otel_default_fields() -> [::opentelemetry::KeyValue; #default_fields_len] {
+                [#(#default_fields),*]
             }
-        }
-        syn::Pat::Tuple(tuple) => {
-            // Handle tuple destructuring like (a, b): (i32, i32)
-            // For now, we'll take the first element
-            if let Some(first_pattern) = tuple.elems.first() {
-                extract_ident_from_pattern(first_pattern)
-            } else {
-                None
+
+            #[doc(hidden)]
+            pub(crate) const _OTEL_BUILD_COMMIT: Option<&str> = #build_commit;
+
+            #[doc(hidden)]
+            pub(crate) const _OTEL_BUILD_TIME: Option<&str> = #build_time;
+
+            /// Resource attributes declared with `tracer_name!(..., resource(...))`, to
+            /// be attached once to the tracer provider's resource rather than every span.
+            #[allow(dead_code)]
+            pub(crate) fn resource_attributes() -> Vec<::opentelemetry::KeyValue> {
+                vec![#(#resource_attrs),*]
             }
-        }
-        syn::Pat::Struct(struct_pat) => {
-            // Handle struct destructuring like User { name, age }: User
-            // For now, we'll take the first field
-            if let Some(first_field) = struct_pat.fields.first() {
-                if let syn::Member::Named(ident) = &first_field.member {
-                    Some(ident.clone())
-                } else {
-                    None
+
+            /// Controls how a value is recorded as a span attribute by `#[instrument]`.
+            /// Types without an impl are recorded using their `Debug` representation.
+            #[allow(dead_code)]
+            pub(crate) trait SpanAttributeValue {
+                fn to_span_value(&self) -> ::opentelemetry::Value;
+            }
+
+            impl<T: SpanAttributeValue + ?Sized> SpanAttributeValue for &T {
+                fn to_span_value(&self) -> ::opentelemetry::Value {
+                    (**self).to_span_value()
                 }
-            } else {
-                None
             }
-        }
-        _ => None,
-    }
-}
 
-fn instrument_impl(
-    args: InstrumentArgs,
-    mut input_fn: ItemFn,
-) -> Result<proc_macro2::TokenStream, syn::Error> {
-    let fn_name = &input_fn.sig.ident;
-    let fn_name_str = fn_name.to_string();
-    let span_name = args.name.unwrap_or(fn_name_str.clone());
+            // Mutable parameters are recorded like shared ones, before the body runs
+            impl<T: SpanAttributeValue + ?Sized> SpanAttributeValue for &mut T {
+                fn to_span_value(&self) -> ::opentelemetry::Value {
+                    (**self).to_span_value()
+                }
+            }
 
-    // Check if function is async
-    let is_async = input_fn.sig.asyncness.is_some();
+            impl SpanAttributeValue for bool {
+                fn to_span_value(&self) -> ::opentelemetry::Value {
+                    ::opentelemetry::Value::Bool(*self)
+                }
+            }
 
-    // Extract function parameters for span attributes and function calls
-    let mut self_ident = None;
-    let mut param_names = Vec::new();
-    let mut param_patterns = Vec::new();
-    
-    for arg in &input_fn.sig.inputs {
-        match arg {
-            syn::FnArg::Typed(pat_type) => {
-                param_patterns.push(pat_type.pat.clone());
-                if let Some(ident) = extract_ident_from_pattern(pat_type.pat.as_ref()) {
-                    param_names.push(ident);
+            impl SpanAttributeValue for str {
+                fn to_span_value(&self) -> ::opentelemetry::Value {
+                    ::opentelemetry::Value::from(self.to_string())
                 }
             }
-            syn::FnArg::Receiver(recv) => {
-                self_ident = Some(Ident::new("self", recv.span()));
+
+            impl SpanAttributeValue for String {
+                fn to_span_value(&self) -> ::opentelemetry::Value {
+                    ::opentelemetry::Value::from(self.clone())
+                }
             }
-        }
-    }
 
-    // Generate span attributes from parameters (respecting skip and skip_all)
-    let span_attrs: Vec<_> = if args.skip_all {
-        Vec::new()
-    } else {
-        param_names.iter()
-            .filter(|name| !args.skip.contains(&name.to_string()))
-            .map(|name| {
-                let name_str = name.to_string();
-                quote! {
-                    span.set_attribute(::opentelemetry::KeyValue::new(#name_str, format!("{:?}", #name)));
+            #(
+                impl SpanAttributeValue for #int_types {
+                    fn to_span_value(&self) -> ::opentelemetry::Value {
+                        ::opentelemetry::Value::I64(i64::from(*self))
+                    }
                 }
-            })
-            .collect()
-    };
+            )*
 
-    // Generate custom field attributes
-    let field_attrs = args.fields.iter().map(|(name, expr)| {
-        quote! {
-            span.set_attribute(::opentelemetry::KeyValue::new(#name, format!("{:?}", #expr)));
+            #(
+                impl SpanAttributeValue for #float_types {
+                    fn to_span_value(&self) -> ::opentelemetry::Value {
+                        ::opentelemetry::Value::F64(f64::from(*self))
+                    }
+                }
+            )*
+
+            /// Marks types whose parameters are recorded by `#[instrument(strict)]`, using
+            /// their `Debug` representation. Types implementing `SpanAttributeValue` are
+            /// always recorded, and parameters of any other type are skipped.
+            #[allow(dead_code)]
+            pub(crate) trait Traceable: ::std::fmt::Debug {}
+
+            impl<T: Traceable + ?Sized> Traceable for &T {}
+            impl<T: Traceable + ?Sized> Traceable for &mut T {}
+            impl<T: Traceable> Traceable for Option<T> {}
+            impl Traceable for bool {}
+            impl Traceable for char {}
+            impl Traceable for str {}
+            impl Traceable for String {}
+            #(impl Traceable for #traceable_types {})*
+
+            /// Types that can be passed as `#[instrument(parent = ...)]`.
+            #[allow(dead_code)]
+            #[diagnostic::on_unimplemented(
+                message = "`{Self}` can't be used as a span parent",
+                label = "expected an `opentelemetry::Context` or `SpanContext`",
+                note = "implement `_otel::ParentContext` for `{Self}` to use it as a parent"
+            )]
+            pub(crate) trait ParentContext {
+                fn parent_context(&self) -> ::opentelemetry::Context;
+            }
+
+            impl<T: ParentContext + ?Sized> ParentContext for &T {
+                fn parent_context(&self) -> ::opentelemetry::Context {
+                    (**self).parent_context()
+                }
+            }
+
+            impl ParentContext for ::opentelemetry::Context {
+                fn parent_context(&self) -> ::opentelemetry::Context {
+                    self.clone()
+                }
+            }
+
+            // Fallible parent sources fall back to the current context, like without `parent`
+            impl<T: ParentContext> ParentContext for Option<T> {
+                fn parent_context(&self) -> ::opentelemetry::Context {
+                    match self {
+                        Some(parent) => parent.parent_context(),
+                        None => ::opentelemetry::Context::current(),
+                    }
+                }
+            }
+
+            impl<T: ParentContext, E> ParentContext for Result<T, E> {
+                fn parent_context(&self) -> ::opentelemetry::Context {
+                    match self {
+                        Ok(parent) => parent.parent_context(),
+                        Err(_) => ::opentelemetry::Context::current(),
+                    }
+                }
+            }
+
+            impl ParentContext for ::opentelemetry::trace::SpanContext {
+                fn parent_context(&self) -> ::opentelemetry::Context {
+                    use ::opentelemetry::trace::TraceContextExt as _;
+                    ::opentelemetry::Context::new().with_remote_span_context(self.clone())
+                }
+            }
+
+            /// Tells whether the body of an `#[instrument]`ed function set the status of its
+            /// span with `set_span_status!`, shared through the span's context.
+            #[doc(hidden)]
+            pub(crate) struct _OtelStatusSet(pub(crate) ::std::sync::Arc<::std::sync::atomic::AtomicBool>);
+
+            /// Map a duration to the label of the first bucket whose upper bound, in
+            /// milliseconds, it is below, for `#[instrument(latency_bucket)]`.
+            #[doc(hidden)]
+            #[allow(dead_code)]
+            pub(crate) fn _otel_latency_bucket(
+                elapsed: ::std::time::Duration,
+                buckets: &[(u64, &'static str)],
+                overflow: &'static str,
+            ) -> &'static str {
+                let millis = elapsed.as_millis();
+                buckets
+                    .iter()
+                    .find(|(bound, _)| millis < *bound as u128)
+                    .map_or(overflow, |(_, label)| label)
+            }
+
+            /// Convert a `parent = ...` expression into the context to start the span in.
+            #[allow(dead_code)]
+            pub(crate) fn into_parent_context<P: ParentContext + ?Sized>(parent: &P) -> ::opentelemetry::Context {
+                parent.parent_context()
+            }
+
+            /// Parse a W3C `traceparent` header value, like
+            /// `00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01`, into a context
+            /// with a remote parent span. Malformed values give an empty context, so the
+            /// span becomes a root span.
+            #[allow(dead_code)]
+            pub(crate) fn traceparent(header: &str) -> ::opentelemetry::Context {
+                use ::opentelemetry::trace::{
+                    SpanContext, SpanId, TraceContextExt as _, TraceFlags, TraceId, TraceState,
+                };
+
+                let parse = || -> Option<SpanContext> {
+                    let mut parts = header.trim().split('-');
+                    let (version, trace_id, span_id, flags) =
+                        (parts.next()?, parts.next()?, parts.next()?, parts.next()?);
+                    let is_hex = |value: &str, len: usize| {
+                        value.len() == len && value.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+                    };
+                    // Version `ff` is invalid, and version `00` has exactly four fields.
+                    // Later versions may append fields, which are ignored.
+                    if !is_hex(version, 2)
+                        || version == "ff"
+                        || (version == "00" && parts.next().is_some())
+                        || !is_hex(trace_id, 32)
+                        || !is_hex(span_id, 16)
+                        || !is_hex(flags, 2)
+                    {
+                        return None;
+                    }
+                    let span_context = SpanContext::new(
+                        TraceId::from_hex(trace_id).ok()?,
+                        SpanId::from_hex(span_id).ok()?,
+                        TraceFlags::new(u8::from_str_radix(flags, 16).ok()?),
+                        true,
+                        TraceState::default(),
+                    );
+                    span_context.is_valid().then_some(span_context)
+                };
+
+                match parse() {
+                    Some(span_context) => ::opentelemetry::Context::new().with_remote_span_context(span_context),
+                    None => ::opentelemetry::Context::new(),
+                }
+            }
+
+            #[doc(hidden)]
+            pub(crate) struct _OtelAttributeValue<'a, T: ?Sized>(pub(crate) &'a T);
+
+            #[doc(hidden)]
+            pub(crate) trait _OtelAttributeViaTrait {
+                fn __otel_attribute_value(&self) -> ::opentelemetry::Value;
+            }
+
+            impl<T: SpanAttributeValue + ?Sized> _OtelAttributeViaTrait for _OtelAttributeValue<'_, T> {
+                fn __otel_attribute_value(&self) -> ::opentelemetry::Value {
+                    self.0.to_span_value()
+                }
+            }
+
+            #[doc(hidden)]
+            pub(crate) trait _OtelAttributeViaDebug {
+                fn __otel_attribute_value(&self) -> ::opentelemetry::Value;
+            }
+
+            impl<T: ::std::fmt::Debug + ?Sized> _OtelAttributeViaDebug for &_OtelAttributeValue<'_, T> {
+                fn __otel_attribute_value(&self) -> ::opentelemetry::Value {
+                    ::opentelemetry::Value::from(format!("{:?}", self.0))
+                }
+            }
+
+            // Strict mode records `SpanAttributeValue` types first, then `Traceable` ones,
+            // and skips anything else. Probed on `&&&_OtelAttributeValue`, so the impl on
+            // the most references is tried first.
+            #[doc(hidden)]
+            pub(crate) trait _OtelStrictViaTrait {
+                fn __otel_strict_value(&self) -> Option<::opentelemetry::Value>;
+            }
+
+            impl<T: SpanAttributeValue + ?Sized> _OtelStrictViaTrait for &&_OtelAttributeValue<'_, T> {
+                fn __otel_strict_value(&self) -> Option<::opentelemetry::Value> {
+                    Some(self.0.to_span_value())
+                }
+            }
+
+            #[doc(hidden)]
+            pub(crate) trait _OtelStrictViaTraceable {
+                fn __otel_strict_value(&self) -> Option<::opentelemetry::Value>;
+            }
+
+            impl<T: Traceable + ?Sized> _OtelStrictViaTraceable for &_OtelAttributeValue<'_, T> {
+                fn __otel_strict_value(&self) -> Option<::opentelemetry::Value> {
+                    Some(::opentelemetry::Value::from(format!("{:?}", self.0)))
+                }
+            }
+
+            #[doc(hidden)]
+            pub(crate) trait _OtelStrictSkip {
+                fn __otel_strict_value(&self) -> Option<::opentelemetry::Value>;
+            }
+
+            impl<T: ?Sized> _OtelStrictSkip for _OtelAttributeValue<'_, T> {
+                fn __otel_strict_value(&self) -> Option<::opentelemetry::Value> {
+                    None
+                }
+            }
+        }
+
+        /// The items of `tracer_name!` meant to be used directly, like `_otel::traceparent`.
+        #[allow(unused_imports)]
+        pub(crate) use _OTEL_TRACER_NAME as _otel;
+    };
+
+    expanded.into()
+}
+
+/// Define the tokio task-local used by `#[instrument(task_local)]`, along with a
+/// `spawn_in_context` helper that carries the current context into a spawned task.
+/// Requires `tokio` as a dependency. Must be in module scope.
+///
+/// # Example
+/// ```rust
+/// use otel_instrument::{task_local_context, tracer_name};
+///
+/// tracer_name!("my-service");
+/// task_local_context!();
+/// ```
+#[proc_macro]
+pub fn task_local_context(_input: TokenStream) -> TokenStream {
+    let expanded = quote! {
+        ::tokio::task_local! {
+            pub(crate) static _OTEL_TASK_CONTEXT: ::opentelemetry::Context;
+        }
+
+        /// Spawn a task that inherits the current OpenTelemetry context, making
+        /// spans created by `#[instrument(task_local)]` functions children of it.
+        #[allow(dead_code)]
+        pub(crate) fn spawn_in_context<F>(future: F) -> ::tokio::task::JoinHandle<F::Output>
+        where
+            F: ::std::future::Future + Send + 'static,
+            F::Output: Send + 'static,
+        {
+            use ::opentelemetry::trace::TraceContextExt as _;
+            let current_ctx = ::opentelemetry::Context::current();
+            let ctx = if current_ctx.has_active_span() {
+                current_ctx
+            } else {
+                _OTEL_TASK_CONTEXT
+                    .try_with(|ctx| ctx.clone())
+                    .unwrap_or(current_ctx)
+            };
+            ::tokio::spawn(_OTEL_TASK_CONTEXT.scope(ctx, future))
+        }
+    };
+
+    expanded.into()
+}
+
+/// Define a `SeededIdGenerator` for the tracer provider, generating predictable
+/// trace and span ids from a seed, so tests can assert against known ids. The n-th
+/// trace id is `(seed << 64) | n` and the n-th span id is `seed + n`, counting from
+/// one. Requires `opentelemetry_sdk` as a dependency. Must be in module scope.
+///
+/// # Example
+/// ```rust
+/// use opentelemetry_sdk::trace::SdkTracerProvider;
+/// use otel_instrument::seeded_id_generator;
+///
+/// seeded_id_generator!();
+///
+/// let provider = SdkTracerProvider::builder()
+///     .with_id_generator(SeededIdGenerator::new(42))
+///     .build();
+/// ```
+#[proc_macro]
+pub fn seeded_id_generator(_input: TokenStream) -> TokenStream {
+    let expanded = quote! {
+        /// Generates predictable trace and span ids from a seed, for tests.
+        #[derive(Debug)]
+        #[allow(dead_code)]
+        pub(crate) struct SeededIdGenerator {
+            seed: u64,
+            traces: ::std::sync::atomic::AtomicU64,
+            spans: ::std::sync::atomic::AtomicU64,
+        }
+
+        #[allow(dead_code)]
+        impl SeededIdGenerator {
+            pub(crate) fn new(seed: u64) -> Self {
+                Self {
+                    seed,
+                    traces: ::std::sync::atomic::AtomicU64::new(0),
+                    spans: ::std::sync::atomic::AtomicU64::new(0),
+                }
+            }
+        }
+
+        impl ::opentelemetry_sdk::trace::IdGenerator for SeededIdGenerator {
+            fn new_trace_id(&self) -> ::opentelemetry::trace::TraceId {
+                let n = self.traces.fetch_add(1, ::std::sync::atomic::Ordering::Relaxed) + 1;
+                ::opentelemetry::trace::TraceId::from(((self.seed as u128) << 64) | n as u128)
+            }
+
+            fn new_span_id(&self) -> ::opentelemetry::trace::SpanId {
+                let n = self.spans.fetch_add(1, ::std::sync::atomic::Ordering::Relaxed) + 1;
+                ::opentelemetry::trace::SpanId::from(self.seed.wrapping_add(n))
+            }
+        }
+    };
+
+    expanded.into()
+}
+
+/// Define `instrument_fn` and `instrument_fn_async` runtime helpers in module scope,
+/// for cases where the attribute can't be used, like generic dispatch tables.
+///
+/// Both helpers create a span with the given name, attributes and kind, run the closure
+/// under it, and set the span status from the returned `Result` like `#[instrument(err)]`.
+/// Requires `tracer_name!` in the same module.
+///
+/// # Example
+/// ```rust
+/// use opentelemetry::{KeyValue, trace::SpanKind};
+/// use otel_instrument::{runtime_instrument, tracer_name};
+///
+/// tracer_name!("my-service");
+/// runtime_instrument!();
+///
+/// fn dispatch(command: &str) -> Result<(), std::fmt::Error> {
+///     instrument_fn(
+///         format!("command.{command}"),
+///         vec![KeyValue::new("command", command.to_string())],
+///         SpanKind::Internal,
+///         || Ok(()),
+///     )
+/// }
+/// ```
+#[proc_macro]
+pub fn runtime_instrument(_input: TokenStream) -> TokenStream {
+    let err_capture = status_capture(Some(&ErrArgs::default()), StatusMode::Auto);
+
+    let expanded = quote! {
+        /// Run `f` inside a new span, mirroring `#[instrument(err)]` for sync code.
+        #[allow(dead_code)]
+        pub(crate) fn instrument_fn<T, E, F>(
+            name: impl Into<::std::borrow::Cow<'static, str>>,
+            attributes: Vec<::opentelemetry::KeyValue>,
+            kind: ::opentelemetry::trace::SpanKind,
+            f: F,
+        ) -> Result<T, E>
+        where
+            E: ::std::error::Error,
+            F: FnOnce() -> Result<T, E>,
+        {
+            use ::opentelemetry::trace::{Span as _, Tracer as _};
+
+            let __otel_tracer = ::opentelemetry::global::tracer_with_scope(_OTEL_TRACER_NAME::_otel_scope());
+            let __otel_span = __otel_tracer
+                .span_builder(name)
+                .with_kind(kind)
+                .with_attributes(attributes)
+                .start(&__otel_tracer);
+            let __otel_guard = ::opentelemetry::trace::mark_span_as_active(__otel_span);
+            let __otel_result = f();
+            #err_capture
+            __otel_result
+        }
+
+        /// Await the future returned by `f` inside a new span, mirroring
+        /// `#[instrument(err)]` for async code.
+        #[allow(dead_code)]
+        pub(crate) async fn instrument_fn_async<T, E, F, Fut>(
+            name: impl Into<::std::borrow::Cow<'static, str>>,
+            attributes: Vec<::opentelemetry::KeyValue>,
+            kind: ::opentelemetry::trace::SpanKind,
+            f: F,
+        ) -> Result<T, E>
+        where
+            E: ::std::error::Error,
+            F: FnOnce() -> Fut,
+            Fut: ::std::future::Future<Output = Result<T, E>>,
+        {
+            use ::opentelemetry::{
+                context::FutureExt as _,
+                trace::{Span as _, TraceContextExt as _, Tracer as _},
+            };
+
+            let __otel_tracer = ::opentelemetry::global::tracer_with_scope(_OTEL_TRACER_NAME::_otel_scope());
+            let __otel_span = __otel_tracer
+                .span_builder(name)
+                .with_kind(kind)
+                .with_attributes(attributes)
+                .start(&__otel_tracer);
+            async move {
+                let __otel_result = f().await;
+                #err_capture
+                __otel_result
+            }
+            .with_context(::opentelemetry::Context::current_with_span(__otel_span))
+            .await
+        }
+    };
+
+    expanded.into()
+}
+
+/// Input of `instrument_closure!`: a span name, a closure and options.
+struct InstrumentClosureInput {
+    name: Expr,
+    closure: syn::ExprClosure,
+    args: InstrumentArgs,
+}
+
+impl Parse for InstrumentClosureInput {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let name = input.parse()?;
+        input.parse::<Token![,]>()?;
+        let closure = input.parse()?;
+        if input.is_empty() {
+            return Ok(Self {
+                name,
+                closure,
+                args: InstrumentArgs::default(),
+            });
+        }
+        input.parse::<Token![,]>()?;
+        let options: proc_macro2::TokenStream = input.parse()?;
+        // Only options that apply to a single call are supported, each starting
+        // with its name after a top level comma
+        let mut option_start = true;
+        for token in options.clone() {
+            match token {
+                proc_macro2::TokenTree::Ident(ident) if option_start => {
+                    if !matches!(
+                        ident.to_string().as_str(),
+                        "skip" | "skip_all" | "fields" | "err"
+                    ) {
+                        return Err(syn::Error::new_spanned(
+                            ident,
+                            "`instrument_closure!` only supports `skip`, `skip_all`, `fields` and `err`",
+                        ));
+                    }
+                    option_start = false;
+                }
+                proc_macro2::TokenTree::Punct(punct) if punct.as_char() == ',' => {
+                    option_start = true;
+                }
+                _ => option_start = false,
+            }
+        }
+        Ok(Self {
+            name,
+            closure,
+            args: syn::parse2(options)?,
+        })
+    }
+}
+
+/// Wrap a closure so each call runs inside a new span, for callbacks stored in
+/// struct fields or passed where a `Fn` or `FnMut` is expected. The closure's
+/// parameters are recorded like function parameters, and `skip`, `skip_all`,
+/// `fields` and `err` are supported like on `#[instrument]`. The name is
+/// evaluated on every call. Requires `tracer_name!` in the same module.
+///
+/// # Example
+/// ```rust
+/// use otel_instrument::{instrument_closure, tracer_name};
+///
+/// tracer_name!("my-service");
+///
+/// struct Handler {
+///     on_message: Box<dyn FnMut(u32) -> Result<(), String>>,
+/// }
+///
+/// let mut seen = 0;
+/// let handler = Handler {
+///     on_message: Box::new(instrument_closure!("on_message", move |id: u32| {
+///         seen += id;
+///         Ok(())
+///     })),
+/// };
+/// ```
+#[proc_macro]
+pub fn instrument_closure(input: TokenStream) -> TokenStream {
+    let InstrumentClosureInput {
+        name,
+        closure,
+        mut args,
+    } = parse_macro_input!(input as InstrumentClosureInput);
+
+    if let Some(asyncness) = &closure.asyncness {
+        return syn::Error::new_spanned(
+            asyncness,
+            "`instrument_closure!` only supports non-async closures",
+        )
+        .to_compile_error()
+        .into();
+    }
+
+    let mut param_names = Vec::new();
+    for input in &closure.inputs {
+        match input {
+            syn::Pat::Type(pat_type) => extract_idents_from_pattern(&pat_type.pat, &mut param_names),
+            pat => extract_idents_from_pattern(pat, &mut param_names),
+        }
+    }
+    let param_attrs = param_names
+        .iter()
+        .filter(|_| !args.skip_all)
+        .filter(|name| !args.skip.contains(&name.to_string()))
+        .map(|name| {
+            let name_str = name.to_string();
+            let value = attribute_value(name);
+            quote! {
+                __otel_span.set_attribute(::opentelemetry::KeyValue::new(#name_str, #value));
+            }
+        });
+    let field_attrs = args
+        .fields
+        .iter()
+        .map(|(name, value)| field_attr(name, value));
+    let err_capture = status_capture(args.err.take().as_ref(), StatusMode::Auto);
+
+    // The body runs in an inner closure, so `return` keeps its meaning, while the
+    // wrapper keeps the original inputs and captures, and so whether it is `Fn` or
+    // `FnMut`
+    let syn::ExprClosure {
+        attrs,
+        movability,
+        capture,
+        inputs,
+        output,
+        body,
+        ..
+    } = &closure;
+    quote! {
+        #(#attrs)* #movability #capture |#inputs| #output {
+            let __otel_guard = {
+                use ::opentelemetry::trace::{Span as _, Tracer as _};
+
+                let __otel_tracer = ::opentelemetry::global::tracer_with_scope(_OTEL_TRACER_NAME::_otel_scope());
+                let mut __otel_span = __otel_tracer.start(#name);
+                #(#param_attrs)*
+                #(#field_attrs)*
+                ::opentelemetry::trace::mark_span_as_active(__otel_span)
+            };
+            let __otel_result = (|| #output #body)();
+            #err_capture
+            __otel_result
+        }
+    }
+    .into()
+}
+
+/// See crate level documentation for usage.
+#[proc_macro_attribute]
+pub fn instrument(args: TokenStream, input: TokenStream) -> TokenStream {
+    let input_fn = parse_macro_input!(input as ItemFn);
+    let args = if args.is_empty() {
+        InstrumentArgs::default()
+    } else {
+        parse_macro_input!(args as InstrumentArgs)
+    };
+
+    match instrument_fn(args, input_fn, None) {
+        Ok((original, wrapper)) => quote!(#original #wrapper).into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+/// Arguments of `#[instrument_impl]`, parsed from an optional
+/// `common_fields(key = value, ...)` list. Each field is kept as tokens, so it can be
+/// added to methods with their own `#[instrument(...)]` attribute, along with
+/// whether it refers to `self`.
+#[derive(Default)]
+struct InstrumentImplArgs {
+    common_fields: Vec<(proc_macro2::TokenStream, bool)>,
+}
+
+/// Check if a token stream refers to the identifier `name` anywhere.
+fn contains_ident(tokens: proc_macro2::TokenStream, name: &str) -> bool {
+    tokens.into_iter().any(|token| match token {
+        proc_macro2::TokenTree::Ident(ident) => ident == name,
+        proc_macro2::TokenTree::Group(group) => contains_ident(group.stream(), name),
+        _ => false,
+    })
+}
+
+/// Check if a function body refers to `Self`. Nested items are skipped, since `Self`
+/// refers to their own type in them.
+fn body_refers_to_self(block: &syn::Block) -> bool {
+    struct FindSelf(bool);
+    impl<'ast> syn::visit::Visit<'ast> for FindSelf {
+        fn visit_item(&mut self, _item: &'ast syn::Item) {}
+        fn visit_ident(&mut self, ident: &'ast Ident) {
+            self.0 |= ident == "Self";
+        }
+        fn visit_macro(&mut self, mac: &'ast syn::Macro) {
+            self.0 |= contains_ident(mac.tokens.clone(), "Self");
+        }
+    }
+    let mut find = FindSelf(false);
+    syn::visit::Visit::visit_block(&mut find, block);
+    find.0
+}
+
+/// Check if a token stream refers to a variable `name`, ignoring fields, methods and
+/// path segments of the same name, like `self.name` or `module::name`.
+fn refers_to_variable(tokens: proc_macro2::TokenStream, name: &str) -> bool {
+    variable_references(tokens, name) > 0
+}
+
+/// Count the references to a variable `name` in a token stream, like
+/// `refers_to_variable`.
+fn variable_references(tokens: proc_macro2::TokenStream, name: &str) -> usize {
+    let tokens: Vec<_> = tokens.into_iter().collect();
+    let is_punct = |token: Option<&proc_macro2::TokenTree>, chars: &[char]| {
+        matches!(token, Some(proc_macro2::TokenTree::Punct(punct)) if chars.contains(&punct.as_char()))
+    };
+    tokens
+        .iter()
+        .enumerate()
+        .map(|(i, token)| match token {
+            proc_macro2::TokenTree::Ident(ident) => usize::from(
+                ident == name
+                    && !is_punct(i.checked_sub(1).and_then(|i| tokens.get(i)), &['.', ':'])
+                    && !is_punct(tokens.get(i + 1), &[':', '!']),
+            ),
+            proc_macro2::TokenTree::Group(group) => variable_references(group.stream(), name),
+            _ => 0,
+        })
+        .sum()
+}
+
+/// Allow the parameters of the original function that the wrapper reads, for example
+/// to record them on the span, to go unused in the body. Any other unused parameter
+/// is still reported. `passed` are the wrapper's tokens passing the parameters to the
+/// original, which don't count as reads.
+fn allow_consumed_params(
+    original: &mut ItemFn,
+    wrapper: &syn::Block,
+    passed: proc_macro2::TokenStream,
+) {
+    let wrapper = quote!(#wrapper);
+    for arg in &mut original.sig.inputs {
+        let syn::FnArg::Typed(pat_type) = arg else {
+            continue;
+        };
+        let mut names = Vec::new();
+        extract_idents_from_pattern(&pat_type.pat, &mut names);
+        let consumed = names.iter().any(|name| {
+            let name = name.to_string();
+            variable_references(wrapper.clone(), &name)
+                > variable_references(passed.clone(), &name)
+        });
+        if consumed {
+            pat_type.attrs.push(syn::parse_quote!(#[allow(unused_variables)]));
+        }
+    }
+}
+
+impl Parse for InstrumentImplArgs {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let mut args = InstrumentImplArgs::default();
+        while !input.is_empty() {
+            let ident: Ident = input.parse()?;
+            if ident != "common_fields" {
+                return Err(syn::Error::new_spanned(
+                    ident,
+                    "Unknown instrument_impl option, use `#[instrument(...)]` on methods instead",
+                ));
+            }
+            let content;
+            syn::parenthesized!(content in input);
+            let tokens: proc_macro2::TokenStream = content.parse()?;
+            // Validate the list up front, so mistakes are reported once on the impl
+            syn::parse::Parser::parse2(parse_fields, tokens.clone())?;
+            // Nested commas are inside groups, so fields split on top level commas
+            let mut field = proc_macro2::TokenStream::new();
+            for token in tokens {
+                match token {
+                    proc_macro2::TokenTree::Punct(punct) if punct.as_char() == ',' => {
+                        let field = std::mem::take(&mut field);
+                        args.common_fields.push((field.clone(), contains_ident(field, "self")));
+                    }
+                    token => field.extend([token]),
+                }
+            }
+            if !field.is_empty() {
+                args.common_fields.push((field.clone(), contains_ident(field, "self")));
+            }
+            if !input.is_empty() {
+                input.parse::<Token![,]>()?;
+            }
+        }
+        Ok(args)
+    }
+}
+
+/// Instrument every method of an `impl` block with the default `#[instrument]`
+/// behavior. Methods with their own `#[instrument(...)]` attribute keep it, so
+/// they can still be customized, and methods marked with `#[skip_instrument]`
+/// are left alone. Associated consts and types are left untouched.
+///
+/// `common_fields(key = value, ...)` records the same fields on every
+/// instrumented method, including those with their own attribute. Fields
+/// referring to `self` are skipped on methods without a `self` receiver.
+///
+/// # Example
+/// ```rust
+/// use otel_instrument::{instrument, instrument_impl, tracer_name};
+///
+/// tracer_name!("my-service");
+///
+/// struct Store {
+///     name: String,
+/// }
+///
+/// #[instrument_impl(common_fields(store = self.name))]
+/// impl Store {
+///     fn get(&self, key: &str) -> Result<String, String> {
+///         Ok(key.to_string())
+///     }
+///
+///     #[instrument(skip(value))]
+///     fn set(&self, key: &str, value: &str) -> Result<(), String> {
+///         Ok(())
+///     }
+///
+///     #[skip_instrument]
+///     fn normalize(key: &str) -> String {
+///         key.to_lowercase()
+///     }
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn instrument_impl(args: TokenStream, input: TokenStream) -> TokenStream {
+    let mut input_impl = parse_macro_input!(input as syn::ItemImpl);
+    let args = parse_macro_input!(args as InstrumentImplArgs);
+
+    // Trait impls can't have extra methods, so the originals of their methods are kept
+    // in a private helper trait implemented for the type instead, where items of the
+    // trait need to be qualified. The helper is named after the impl, so several trait
+    // impls on one type each get their own.
+    let trait_path = input_impl.trait_.as_ref().map(|(_, path, _)| path.clone());
+    let helper = trait_path.as_ref().map(|trait_path| {
+        let self_ty = &input_impl.self_ty;
+        let mut hasher = std::hash::DefaultHasher::new();
+        std::hash::Hash::hash(&quote!(#trait_path for #self_ty).to_string(), &mut hasher);
+        let trait_name = trait_path
+            .segments
+            .last()
+            .map(|segment| segment.ident.to_string())
+            .unwrap_or_default();
+        quote::format_ident!(
+            "__Otel{}Originals{:X}",
+            trait_name,
+            std::hash::Hasher::finish(&hasher)
+        )
+    });
+    // Lifetimes elided in the trait path are named for the helper, which declares them,
+    // and inferred where the wrapper calls it
+    let mut helper_trait_path = trait_path.clone();
+    let elided = helper_trait_path
+        .as_mut()
+        .map(name_elided_lifetimes)
+        .unwrap_or_default();
+    let with_lifetimes = |lifetimes: &[syn::Lifetime]| {
+        let mut generics = input_impl.generics.clone();
+        for lifetime in lifetimes.iter().rev() {
+            let param = syn::LifetimeParam::new(lifetime.clone());
+            generics.params.insert(0, syn::GenericParam::Lifetime(param));
+        }
+        generics
+    };
+    let helper_generics = with_lifetimes(&elided);
+    let inferred = vec![syn::Lifetime::new("'_", proc_macro2::Span::call_site()); elided.len()];
+    let dispatch_generics = with_lifetimes(&inferred);
+    let (_, dispatch_ty_generics, _) = dispatch_generics.split_for_impl();
+    let dispatch = match &helper {
+        Some(helper) => quote!(<Self as #helper #dispatch_ty_generics>),
+        None => quote!(Self),
+    };
+    let trait_items: HashSet<String> = input_impl
+        .items
+        .iter()
+        .filter_map(|item| match item {
+            syn::ImplItem::Fn(method) => Some(method.sig.ident.to_string()),
+            syn::ImplItem::Const(item_const) => Some(item_const.ident.to_string()),
+            syn::ImplItem::Type(item_type) => Some(item_type.ident.to_string()),
+            _ => None,
+        })
+        .collect();
+    let mut originals = Vec::new();
+
+    let mut items = Vec::new();
+    for item in std::mem::take(&mut input_impl.items) {
+        let syn::ImplItem::Fn(mut method) = item else {
+            items.push(item);
+            continue;
+        };
+        let is_attr = |attr: &syn::Attribute, name: &str| {
+            attr.path()
+                .segments
+                .last()
+                .is_some_and(|segment| segment.ident == name)
+        };
+        // The marker is removed here, so it doesn't need to be imported
+        let attrs_len = method.attrs.len();
+        method.attrs.retain(|attr| !is_attr(attr, "skip_instrument"));
+        if method.attrs.len() != attrs_len {
+            items.push(syn::ImplItem::Fn(method));
+            continue;
+        }
+
+        let has_self = method.sig.receiver().is_some();
+        let common_fields: Vec<_> = args
+            .common_fields
+            .iter()
+            .filter(|(_, uses_self)| has_self || !uses_self)
+            .map(|(field, _)| field)
+            .collect();
+        let common_fields = if common_fields.is_empty() {
+            proc_macro2::TokenStream::new()
+        } else {
+            quote!(fields(#(#common_fields),*))
+        };
+
+        // Methods with their own attribute are instrumented with its arguments, and
+        // get the common fields appended to them, as repeated `fields(...)` lists
+        // accumulate
+        let tokens = match method
+            .attrs
+            .iter()
+            .position(|attr| is_attr(attr, "instrument"))
+        {
+            Some(index) => match method.attrs.remove(index).meta {
+                syn::Meta::List(list) if !list.tokens.is_empty() && !common_fields.is_empty() => {
+                    let tokens = list.tokens;
+                    quote!(#tokens, #common_fields)
+                }
+                syn::Meta::List(list) if !list.tokens.is_empty() => list.tokens,
+                syn::Meta::Path(_) | syn::Meta::List(_) => common_fields,
+                syn::Meta::NameValue(meta) => {
+                    return syn::Error::new_spanned(
+                        meta,
+                        "Expected `#[instrument]` or `#[instrument(...)]`",
+                    )
+                    .to_compile_error()
+                    .into();
+                }
+            },
+            None => common_fields,
+        };
+        let instrument_args = if tokens.is_empty() {
+            Ok(InstrumentArgs::default())
+        } else {
+            syn::parse2::<InstrumentArgs>(tokens)
+        };
+        let input_fn = ItemFn {
+            attrs: std::mem::take(&mut method.attrs),
+            vis: method.vis.clone(),
+            sig: method.sig.clone(),
+            block: Box::new(method.block.clone()),
+        };
+        let (original, wrapper) =
+            match instrument_args.and_then(|args| instrument_fn(args, input_fn, Some(&dispatch))) {
+                Ok(instrumented) => instrumented,
+                Err(err) => return err.to_compile_error().into(),
+            };
+        method.attrs = wrapper.attrs;
+        method.sig = wrapper.sig;
+        method.block = *wrapper.block;
+        let original = syn::ImplItemFn {
+            attrs: original.attrs,
+            vis: original.vis,
+            defaultness: None,
+            sig: original.sig,
+            block: *original.block,
+        };
+        items.push(syn::ImplItem::Fn(method));
+        match &helper_trait_path {
+            Some(trait_path) => {
+                let original = qualify_trait_items(quote!(#original), trait_path, &trait_items);
+                match syn::parse2::<syn::ImplItemFn>(original) {
+                    Ok(original) => originals.push(original),
+                    Err(err) => return err.to_compile_error().into(),
+                }
+            }
+            None => items.push(syn::ImplItem::Fn(original)),
+        }
+    }
+    input_impl.items = items;
+
+    // The helper trait extends the implemented trait, so its items can be used in the
+    // signatures. Declarations can't have patterns, so parameters are left unnamed.
+    let originals_impl = helper.filter(|_| !originals.is_empty()).map(|helper| {
+        let (impl_generics, ty_generics, where_clause) = helper_generics.split_for_impl();
+        let self_ty = &input_impl.self_ty;
+        let declarations = originals.iter().map(|original| {
+            let mut sig = original.sig.clone();
+            for arg in &mut sig.inputs {
+                match arg {
+                    syn::FnArg::Receiver(receiver) => receiver.mutability = None,
+                    syn::FnArg::Typed(pat_type) => {
+                        pat_type.attrs.clear();
+                        *pat_type.pat = syn::parse_quote!(_);
+                    }
+                }
+            }
+            quote!(#sig;)
+        });
+        quote! {
+            #[doc(hidden)]
+            trait #helper #impl_generics: #helper_trait_path #where_clause {
+                #(#declarations)*
+            }
+
+            impl #impl_generics #helper #ty_generics for #self_ty #where_clause {
+                #(#originals)*
+            }
+        }
+    });
+
+    quote! {
+        #input_impl
+        #originals_impl
+    }
+    .into()
+}
+
+/// Name the lifetimes elided in a trait path, like in `TryFrom<&str>`, which can only
+/// be elided in an impl header, returning the names to declare.
+fn name_elided_lifetimes(path: &mut syn::Path) -> Vec<syn::Lifetime> {
+    struct NameLifetimes(Vec<syn::Lifetime>);
+    impl NameLifetimes {
+        fn next(&mut self) -> syn::Lifetime {
+            let name = format!("'__otel{}", self.0.len());
+            let lifetime = syn::Lifetime::new(&name, proc_macro2::Span::call_site());
+            self.0.push(lifetime.clone());
+            lifetime
+        }
+    }
+    impl syn::visit_mut::VisitMut for NameLifetimes {
+        fn visit_type_reference_mut(&mut self, reference: &mut syn::TypeReference) {
+            if reference.lifetime.is_none() {
+                reference.lifetime = Some(self.next());
+            }
+            syn::visit_mut::visit_type_reference_mut(self, reference);
+        }
+        fn visit_lifetime_mut(&mut self, lifetime: &mut syn::Lifetime) {
+            if lifetime.ident == "_" {
+                *lifetime = self.next();
+            }
+        }
+        // Function pointers and `Fn` traits elide lifetimes on their own
+        fn visit_type_bare_fn_mut(&mut self, _bare_fn: &mut syn::TypeBareFn) {}
+        fn visit_parenthesized_generic_arguments_mut(
+            &mut self,
+            _arguments: &mut syn::ParenthesizedGenericArguments,
+        ) {
+        }
+    }
+    let mut names = NameLifetimes(Vec::new());
+    syn::visit_mut::VisitMut::visit_path_mut(&mut names, path);
+    names.0
+}
+
+/// Qualify paths like `Self::Output` to items of the implemented trait, which are
+/// ambiguous outside of the trait impl, as `<Self as Trait>::Output`.
+fn qualify_trait_items(
+    tokens: proc_macro2::TokenStream,
+    trait_path: &syn::Path,
+    names: &HashSet<String>,
+) -> proc_macro2::TokenStream {
+    let tokens: Vec<_> = tokens.into_iter().collect();
+    let is_colon = |token: Option<&proc_macro2::TokenTree>| {
+        matches!(token, Some(proc_macro2::TokenTree::Punct(punct)) if punct.as_char() == ':')
+    };
+    let mut qualified = proc_macro2::TokenStream::new();
+    for (i, token) in tokens.iter().enumerate() {
+        match token {
+            proc_macro2::TokenTree::Ident(ident)
+                if ident == "Self"
+                    && is_colon(tokens.get(i + 1))
+                    && is_colon(tokens.get(i + 2))
+                    && matches!(tokens.get(i + 3), Some(proc_macro2::TokenTree::Ident(name))
+                        if names.contains(&name.to_string())) =>
+            {
+                qualified.extend(quote::quote_spanned!(ident.span()=> <Self as #trait_path>));
+            }
+            proc_macro2::TokenTree::Group(group) => {
+                let mut qualified_group = proc_macro2::Group::new(
+                    group.delimiter(),
+                    qualify_trait_items(group.stream(), trait_path, names),
+                );
+                qualified_group.set_span(group.span());
+                qualified.extend([proc_macro2::TokenTree::Group(qualified_group)]);
+            }
+            token => qualified.extend([token.clone()]),
+        }
+    }
+    qualified
+}
+
+/// Exclude a method from `#[instrument_impl]`. Outside of an instrumented `impl`
+/// block, the marker has no effect.
+#[proc_macro_attribute]
+pub fn skip_instrument(_args: TokenStream, input: TokenStream) -> TokenStream {
+    input
+}
+
+/// Record a timeline marker on the active span, as an event with the time elapsed
+/// since the previous checkpoint, or since the function started. Can only be used
+/// directly in the body of an `#[instrument]`ed function, which keeps track of the
+/// previous checkpoint.
+///
+/// # Example
+/// ```rust
+/// use otel_instrument::{checkpoint, instrument, tracer_name};
+///
+/// tracer_name!("my-service");
+///
+/// #[instrument]
+/// fn handle(id: u64) -> Result<(), String> {
+///     // ... load the record
+///     checkpoint!("db_query");
+///     // ... render the response
+///     checkpoint!("render");
+///     Ok(())
+/// }
+/// ```
+#[proc_macro]
+pub fn checkpoint(input: TokenStream) -> TokenStream {
+    let name = parse_macro_input!(input as Expr);
+    quote! {{
+        let __otel_now = ::std::time::Instant::now();
+        let __otel_elapsed = __otel_now.duration_since(__otel_checkpoint.replace(__otel_now));
+        ::opentelemetry::trace::get_active_span(|__otel_span| {
+            __otel_span.add_event(
+                #name,
+                vec![::opentelemetry::KeyValue::new(
+                    "elapsed_ms",
+                    __otel_elapsed.as_secs_f64() * 1000.0,
+                )],
+            );
+        });
+    }}
+    .into()
+}
+
+/// Set the status of the active span from the body of an `#[instrument]`ed
+/// function. The status is kept instead of the one derived from the function's
+/// result, while error attributes and events are still recorded. Can only be used
+/// directly in the body of an `#[instrument]`ed function, which shares a flag
+/// through the span's context to tell whether the status was set.
+///
+/// # Example
+/// ```rust
+/// use opentelemetry::trace::Status;
+/// use otel_instrument::{instrument, set_span_status, tracer_name};
+///
+/// tracer_name!("my-service");
+///
+/// #[instrument]
+/// fn sync_batch(items: &[u64]) -> Result<usize, String> {
+///     let synced = items.len() / 2;
+///     if synced < items.len() {
+///         // Partial failures still return `Ok`, but shouldn't look successful
+///         set_span_status!(Status::error("partially synced"));
+///     }
+///     Ok(synced)
+/// }
+/// ```
+#[proc_macro]
+pub fn set_span_status(input: TokenStream) -> TokenStream {
+    let status = parse_macro_input!(input as Expr);
+    quote! {{
+        if let Some(__otel_status_set) = ::opentelemetry::Context::current().get::<_OTEL_TRACER_NAME::_OtelStatusSet>() {
+            __otel_status_set.0.store(true, ::std::sync::atomic::Ordering::Relaxed);
+        }
+        ::opentelemetry::trace::get_active_span(|__otel_span| {
+            __otel_span.set_status(#status);
+        });
+    }}
+    .into()
+}
+
+/// Check if a token stream invokes the macro `name!` anywhere.
+fn invokes_macro(tokens: proc_macro2::TokenStream, name: &str) -> bool {
+    find_macro(tokens, name).is_some()
+}
+
+/// Find the first invocation of the macro `name!` in a token stream, returning the
+/// macro's name.
+fn find_macro(tokens: proc_macro2::TokenStream, name: &str) -> Option<Ident> {
+    let mut tokens = tokens.into_iter().peekable();
+    while let Some(token) = tokens.next() {
+        match token {
+            proc_macro2::TokenTree::Ident(ident)
+                if ident == name
+                    && matches!(tokens.peek(), Some(proc_macro2::TokenTree::Punct(punct)) if punct.as_char() == '!') =>
+            {
+                return Some(ident);
+            }
+            proc_macro2::TokenTree::Group(group) => {
+                if let Some(ident) = find_macro(group.stream(), name) {
+                    return Some(ident);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Derive a `variant_name()` method returning the name of an enum's variant,
+/// without its fields. Used by `fields(kind = variant(event))` to record
+/// low-cardinality attributes.
+///
+/// # Example
+/// ```rust
+/// use otel_instrument::VariantName;
+///
+/// #[derive(VariantName)]
+/// enum Event {
+///     Created { id: u64 },
+///     Deleted(u64),
+///     Cleared,
+/// }
+///
+/// assert_eq!(Event::Created { id: 1 }.variant_name(), "Created");
+/// assert_eq!(Event::Cleared.variant_name(), "Cleared");
+/// ```
+#[proc_macro_derive(VariantName)]
+pub fn derive_variant_name(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as syn::DeriveInput);
+    let syn::Data::Enum(data) = &input.data else {
+        return syn::Error::new_spanned(
+            &input.ident,
+            "`VariantName` can only be derived for enums",
+        )
+        .to_compile_error()
+        .into();
+    };
+
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let arms = data.variants.iter().map(|variant| {
+        let variant_ident = &variant.ident;
+        let name = variant_ident.to_string();
+        quote! { Self::#variant_ident { .. } => #name }
+    });
+
+    quote! {
+        impl #impl_generics #ident #ty_generics #where_clause {
+            /// The name of this variant, without its fields.
+            #[allow(dead_code)]
+            pub fn variant_name(&self) -> &'static str {
+                match self {
+                    #(#arms,)*
+                }
+            }
+        }
+    }
+    .into()
+}
+
+/// Collect every name bound by a parameter pattern, so destructured parameters like
+/// `(a, b): (i32, i32)` or `User { name, age }: User` record each binding.
+fn extract_idents_from_pattern(pat: &syn::Pat, idents: &mut Vec<Ident>) {
+    match pat {
+        syn::Pat::Ident(ident) => {
+            idents.push(ident.ident.clone());
+            // Handle bindings with subpatterns like `all @ (first, _)`
+            if let Some((_, subpat)) = &ident.subpat {
+                extract_idents_from_pattern(subpat, idents);
+            }
+        }
+        syn::Pat::TupleStruct(tuple_struct) => {
+            // Handle patterns like State(state): State<AppState>
+            for elem in &tuple_struct.elems {
+                extract_idents_from_pattern(elem, idents);
+            }
+        }
+        syn::Pat::Tuple(tuple) => {
+            for elem in &tuple.elems {
+                extract_idents_from_pattern(elem, idents);
+            }
+        }
+        syn::Pat::Struct(struct_pat) => {
+            for field in &struct_pat.fields {
+                extract_idents_from_pattern(&field.pat, idents);
+            }
+        }
+        syn::Pat::Reference(reference) => extract_idents_from_pattern(&reference.pat, idents),
+        syn::Pat::Slice(slice) => {
+            for elem in &slice.elems {
+                extract_idents_from_pattern(elem, idents);
+            }
+        }
+        syn::Pat::Paren(paren) => extract_idents_from_pattern(&paren.pat, idents),
+        _ => {}
+    }
+}
+
+/// Get the generic arguments of the return type if it is spelled as a `Result<..>`.
+/// Only the last path segment is checked, so aliases like `io::Result<T>` or a
+/// crate's own `type Result<T> = ...` match too, in which case the first argument
+/// is still the `Ok` type. Aliases with another name can't be resolved by a macro.
+fn result_generics(
+    output: &syn::ReturnType,
+) -> Option<&syn::punctuated::Punctuated<syn::GenericArgument, Token![,]>> {
+    let syn::ReturnType::Type(_, ty) = output else {
+        return None;
+    };
+    let syn::Type::Path(type_path) = ty.as_ref() else {
+        return None;
+    };
+    let segment = type_path.path.segments.last()?;
+    if segment.ident != "Result" {
+        return None;
+    }
+    let syn::PathArguments::AngleBracketed(generics) = &segment.arguments else {
+        return None;
+    };
+    Some(&generics.args)
+}
+
+/// Check if the return type is one of `names`, matched by its last path segment.
+fn returns_type_named(output: &syn::ReturnType, names: &[&str]) -> bool {
+    let syn::ReturnType::Type(_, ty) = output else {
+        return false;
+    };
+    let syn::Type::Path(type_path) = ty.as_ref() else {
+        return false;
+    };
+    type_path
+        .path
+        .segments
+        .last()
+        .is_some_and(|segment| names.iter().any(|name| segment.ident == name))
+}
+
+/// Check if the return type is a `Poll`, like `std::task::Poll<T>`.
+fn returns_poll(output: &syn::ReturnType) -> bool {
+    returns_type_named(output, &["Poll"])
+}
+
+/// Check if the return type is a boxed future, like the `Pin<Box<dyn Future>>`
+/// produced by macros rewriting async functions, such as `#[async_recursion]`.
+fn returns_boxed_future(output: &syn::ReturnType) -> bool {
+    returns_type_named(output, &["Pin", "BoxFuture"])
+}
+
+/// How the success value is formatted for the `return` attribute.
+enum RetFormat {
+    Debug,
+    Display,
+    /// An opaque `impl Trait` without a formatting bound, which can't be recorded,
+    /// with the span of the type.
+    Opaque(proc_macro2::Span),
+}
+
+/// Pick the format for the success value. `impl Trait` types can only be formatted
+/// through their bounds, so `Display` is used for `impl Display` and the value is
+/// skipped if neither `Debug` nor `Display` is a bound.
+fn ret_format(output: &syn::ReturnType, display: bool) -> RetFormat {
+    let Some(syn::GenericArgument::Type(syn::Type::ImplTrait(impl_trait))) =
+        result_generics(output).and_then(|args| args.first())
+    else {
+        return if display {
+            RetFormat::Display
+        } else {
+            RetFormat::Debug
+        };
+    };
+    let has_bound = |name: &str| {
+        impl_trait.bounds.iter().any(|bound| {
+            matches!(bound, syn::TypeParamBound::Trait(bound)
+                if bound.path.segments.last().is_some_and(|segment| segment.ident == name))
+        })
+    };
+    if has_bound("Display") && (display || !has_bound("Debug")) {
+        RetFormat::Display
+    } else if has_bound("Debug") {
+        RetFormat::Debug
+    } else {
+        RetFormat::Opaque(syn::spanned::Spanned::span(impl_trait))
+    }
+}
+
+/// Check if the return type is a `Result` whose `Ok` type is the unit type.
+fn returns_unit_result(output: &syn::ReturnType) -> bool {
+    matches!(
+        result_generics(output).and_then(|args| args.first()),
+        Some(syn::GenericArgument::Type(syn::Type::Tuple(tuple))) if tuple.elems.is_empty()
+    )
+}
+
+/// Check if the return type is a `Result` whose error type is a `Box<dyn ..>`.
+fn returns_boxed_error(output: &syn::ReturnType) -> bool {
+    let Some(syn::GenericArgument::Type(syn::Type::Path(err_path))) =
+        result_generics(output).and_then(|args| args.iter().nth(1))
+    else {
+        return false;
+    };
+    let Some(segment) = err_path.path.segments.last() else {
+        return false;
+    };
+    let syn::PathArguments::AngleBracketed(generics) = &segment.arguments else {
+        return false;
+    };
+    segment.ident == "Box"
+        && matches!(
+            generics.args.first(),
+            Some(syn::GenericArgument::Type(syn::Type::TraitObject(_)))
+        )
+}
+
+/// How the span status is set from the function's result.
+#[derive(Clone, Copy, PartialEq)]
+enum StatusMode {
+    /// Always set the status.
+    Auto,
+    /// Set the status unless the body already set it with `set_span_status!`.
+    Overridable,
+    /// Never set the status, which `status = manual` leaves to the body.
+    Manual,
+}
+
+/// Generate the code setting the span status from `result`, recording error
+/// details when `err` is enabled. Shared by `#[instrument]` and the runtime API.
+fn status_capture(err: Option<&ErrArgs>, mode: StatusMode) -> proc_macro2::TokenStream {
+    let set_status = |status: proc_macro2::TokenStream| match mode {
+        StatusMode::Auto => quote! { __otel_span.set_status(#status); },
+        StatusMode::Overridable => quote! {
+            if !__otel_status_set.load(::std::sync::atomic::Ordering::Relaxed) {
+                __otel_span.set_status(#status);
+            }
+        },
+        StatusMode::Manual => proc_macro2::TokenStream::new(),
+    };
+    let ok_status = set_status(quote!(::opentelemetry::trace::Status::Ok));
+    if let Some(ErrArgs {
+        record,
+        retryable,
+        record_if,
+        log,
+        no_attr,
+        variant,
+        attrs,
+    }) = err
+    {
+        let record = record.clone().unwrap_or_else(|| syn::parse_quote!(e));
+        // The matched error uses a mixed-site binding so it can't collide with anything
+        // in the user's scope. User expressions get it explicitly bound as `e`.
+        let error = Ident::new("error", proc_macro2::Span::mixed_site());
+        let error_status = set_status(quote! {
+            ::opentelemetry::trace::Status::error(format!("{:?}", #error))
+        });
+        // Retryable errors keep the status unset and are flagged with an attribute instead
+        let err_status = if let Some(retryable) = retryable {
+            quote! {
+                if { let e = #error; #retryable } {
+                    __otel_span.set_attribute(::opentelemetry::KeyValue::new("error.retryable", true));
+                } else {
+                    #error_status
+                }
+            }
+        } else {
+            quote! {
+                #error_status
+            }
+        };
+        let err_log = log
+            .then(|| quote! { ::log::error!("{:?}", #error); })
+            .unwrap_or_default();
+        let err_attr = if *no_attr {
+            proc_macro2::TokenStream::new()
+        } else {
+            quote! {
+                __otel_span.set_attribute(::opentelemetry::KeyValue::new("error", format!("{:?}", #error)));
+            }
+        };
+        let derived_attrs = attrs.iter().map(|(name, expr)| {
+            let value = attribute_value(quote!({ let e = #error; #expr }));
+            quote! {
+                __otel_span.set_attribute(::opentelemetry::KeyValue::new(#name, #value));
+            }
+        });
+        // `variant_name` is generated by `#[derive(VariantName)]`
+        let variant_attr = variant.then(|| {
+            quote! {
+                __otel_span.set_attribute(::opentelemetry::KeyValue::new("error.variant", #error.variant_name()));
+            }
+        });
+        let err_attr = quote! {
+            #err_attr
+            #variant_attr
+            #(#derived_attrs)*
+        };
+        let err_record = quote! {
+            #err_log
+            ::opentelemetry::trace::get_active_span(|__otel_span| {
+                #err_attr
+                #err_status
+                __otel_span.record_error({ let e = #error; #record });
+            });
+        };
+        // Expected errors are only recorded as attributes, leaving the status unset
+        let err_record = if let Some(record_if) = record_if {
+            quote! {
+                if { let e = #error; #record_if } {
+                    #err_record
+                } else {
+                    ::opentelemetry::trace::get_active_span(|__otel_span| {
+                        #err_attr
+                        __otel_span.set_attribute(::opentelemetry::KeyValue::new("error.expected", true));
+                    });
+                }
+            }
+        } else {
+            err_record
+        };
+        quote! {
+            match &__otel_result {
+                Ok(_) => {
+                    ::opentelemetry::trace::get_active_span(|__otel_span| {
+                        #ok_status
+                    });
+                }
+                Err(#error) => {
+                    #err_record
+                }
+            }
         }
+    } else {
+        // Failures are still visible from the status, while error details are only
+        // recorded with `err`, so the error type needs no bounds. The description
+        // names the error type, which is known without any.
+        let error = Ident::new("error", proc_macro2::Span::mixed_site());
+        let error_status = set_status(quote! {
+            ::opentelemetry::trace::Status::error(::std::any::type_name_of_val(#error))
+        });
+        quote! {
+            match &__otel_result {
+                Ok(_) => {
+                    ::opentelemetry::trace::get_active_span(|__otel_span| {
+                        #ok_status
+                    });
+                }
+                Err(#error) => {
+                    ::opentelemetry::trace::get_active_span(|__otel_span| {
+                        #error_status
+                    });
+                }
+            }
+        }
+    }
+}
+
+/// The function defined by `tracer_name!` returning the instrumentation scope, either
+/// the default one or a named one selected with `tracer = ...`.
+fn scope_fn(args: &InstrumentArgs) -> Ident {
+    match &args.tracer {
+        // Spanned to the name, so an undeclared scope is reported there
+        Some(tracer) => quote::format_ident!("_otel_scope_{}", tracer, span = tracer.span()),
+        None => Ident::new("_otel_scope", proc_macro2::Span::call_site()),
+    }
+}
+
+/// Generate the lookup of the tracer used by `#[instrument]`, as a reference. With
+/// the `cached_tracer` feature, it is cached by `tracer_name!` after the first call.
+fn tracer_lookup(args: &InstrumentArgs) -> proc_macro2::TokenStream {
+    let scope_fn = scope_fn(args);
+    if cfg!(feature = "cached_tracer") {
+        // Spanned like the scope, so an undeclared scope is reported on its name
+        let tracer_fn = Ident::new(
+            &scope_fn.to_string().replacen("_otel_scope", "_otel_tracer", 1),
+            scope_fn.span(),
+        );
+        quote!(_OTEL_TRACER_NAME::#tracer_fn())
+    } else {
+        quote!(&::opentelemetry::global::tracer_with_scope(_OTEL_TRACER_NAME::#scope_fn()))
+    }
+}
+
+/// Generate a warning for every legacy form that was used, or other note. Proc macros
+/// can't emit warnings on stable, so a `#[deprecated]` item is used instead, spanned
+/// to the tokens it is about. The warning can be silenced with `#[allow(deprecated)]`.
+fn deprecation_warnings(args: &InstrumentArgs) -> proc_macro2::TokenStream {
+    let warnings = args.deprecations.iter().map(|(span, note)| {
+        quote::quote_spanned! {*span=>
+            {
+                #[deprecated(note = #note)]
+                struct __OtelDeprecated;
+                let _ = __OtelDeprecated;
+            }
+        }
+    });
+    quote!(#(#warnings)*)
+}
+
+/// Instrument a function with metrics only, recording its duration and call count
+/// without creating a span. The instruments are created on the first call, from the
+/// meter provider set at that point.
+fn metric_only_fn(
+    args: &InstrumentArgs,
+    mut input_fn: ItemFn,
+    dispatch: Option<&proc_macro2::TokenStream>,
+    span_name: proc_macro2::TokenStream,
+    is_poll: bool,
+) -> Result<(ItemFn, ItemFn), syn::Error> {
+    // Only options naming the function and its scope make sense without a span
+    if let Some(option) = args.options.iter().find(|option| {
+        !matches!(
+            option.to_string().as_str(),
+            "metric_only" | "name" | "qualified_name" | "tracer"
+        )
+    }) {
+        return Err(syn::Error::new_spanned(
+            option,
+            format!("`{option}` needs a span, so it can't be combined with `metric_only`"),
+        ));
+    }
+    // The macros used in the body act on the active span, which would be the caller's
+    let block = &input_fn.block;
+    if let Some(invocation) = ["checkpoint", "set_span_status"]
+        .into_iter()
+        .find_map(|name| find_macro(quote!(#block), name))
+    {
+        return Err(syn::Error::new_spanned(
+            &invocation,
+            format!("`{invocation}!` needs a span, so it can't be used with `metric_only`"),
+        ));
+    }
+
+    let Original {
+        item: original,
+        bindings,
+        call,
+    } = split_original(&mut input_fn, dispatch, proc_macro2::TokenStream::new())?;
+    // `Poll` has no error, and `Pending` is a normal outcome
+    let error_attr = (!is_poll).then_some(quote! {
+        ::opentelemetry::KeyValue::new("error", __otel_result.is_err()),
+    });
+
+    let scope_fn = scope_fn(args);
+    let deprecations = deprecation_warnings(args);
+    let instrumented_body = quote! {
+        {
+            #deprecations
+            #bindings
+            static __OTEL_METRICS: ::std::sync::OnceLock<(
+                ::opentelemetry::metrics::Histogram<f64>,
+                ::opentelemetry::metrics::Counter<u64>,
+            )> = ::std::sync::OnceLock::new();
+            let (__otel_duration, __otel_calls) = __OTEL_METRICS.get_or_init(|| {
+                let __otel_meter = ::opentelemetry::global::meter_with_scope(_OTEL_TRACER_NAME::#scope_fn());
+                (
+                    __otel_meter.f64_histogram("function.duration").with_unit("s").build(),
+                    __otel_meter.u64_counter("function.calls").build(),
+                )
+            });
+            // The name is evaluated before the body, which may move the parameters
+            let __otel_function = #span_name;
+            let __otel_start = ::std::time::Instant::now();
+            let __otel_result = #call;
+            let __otel_attributes = [
+                ::opentelemetry::KeyValue::new("code.function", __otel_function),
+                #error_attr
+            ];
+            __otel_duration.record(__otel_start.elapsed().as_secs_f64(), &__otel_attributes);
+            __otel_calls.add(1, &__otel_attributes);
+            __otel_result
+        }
+    };
+
+    input_fn.block = syn::parse2(instrumented_body)?;
+    let mut original = original;
+    allow_consumed_params(&mut original, &input_fn.block, quote!(#bindings #call));
+
+    Ok((original, input_fn))
+}
+
+/// Generate the conversion of a value into an `opentelemetry::Value`, preferring the
+/// `SpanAttributeValue` impl from `tracer_name!` and falling back to `Debug` formatting
+/// through autoref specialization. Spanned to the expression, so a value that can't
+/// be recorded is reported on the user's tokens rather than the whole attribute.
+fn attribute_value(expr: impl quote::ToTokens) -> proc_macro2::TokenStream {
+    let span = syn::spanned::Spanned::span(&expr);
+    quote::quote_spanned! {span=> {
+        #[allow(unused_imports)]
+        use _OTEL_TRACER_NAME::{_OtelAttributeViaDebug as _, _OtelAttributeViaTrait as _};
+        (&_OTEL_TRACER_NAME::_OtelAttributeValue(&(#expr))).__otel_attribute_value()
+    }}
+}
+
+/// Generate the conversion of a parameter into an `Option<opentelemetry::Value>` for
+/// `#[instrument(strict)]`, which is `None` unless the type implements
+/// `SpanAttributeValue` or `Traceable`.
+fn strict_attribute_value(expr: impl quote::ToTokens) -> proc_macro2::TokenStream {
+    let span = syn::spanned::Spanned::span(&expr);
+    quote::quote_spanned! {span=> {
+        #[allow(unused_imports)]
+        use _OTEL_TRACER_NAME::{
+            _OtelStrictSkip as _, _OtelStrictViaTraceable as _, _OtelStrictViaTrait as _,
+        };
+        (&&&_OTEL_TRACER_NAME::_OtelAttributeValue(&(#expr))).__otel_strict_value()
+    }}
+}
+
+/// Find the `Stream` trait of an `impl Stream<Item = T>` return type, without its
+/// arguments, so a wrapper stream can implement the trait the function returns.
+fn stream_trait(output: &syn::ReturnType) -> Option<syn::Path> {
+    let syn::ReturnType::Type(_, ty) = output else {
+        return None;
+    };
+    let syn::Type::ImplTrait(impl_trait) = ty.as_ref() else {
+        return None;
+    };
+    impl_trait.bounds.iter().find_map(|bound| match bound {
+        syn::TypeParamBound::Trait(bound)
+            if bound
+                .path
+                .segments
+                .last()
+                .is_some_and(|segment| segment.ident == "Stream") =>
+        {
+            let mut path = bound.path.clone();
+            if let Some(segment) = path.segments.last_mut() {
+                segment.arguments = syn::PathArguments::None;
+            }
+            Some(path)
+        }
+        _ => None,
+    })
+}
+
+/// Forward the type and const generics of the wrapper to the original function, so
+/// generics that can't be inferred from the arguments or return type still resolve.
+/// Lifetimes are left out, since late-bound lifetimes can't be specified explicitly,
+/// and nothing is forwarded when `impl Trait` arguments are present, which forbid it.
+fn generic_turbofish(sig: &syn::Signature) -> proc_macro2::TokenStream {
+    let generic_args: Vec<_> = sig
+        .generics
+        .params
+        .iter()
+        .filter_map(|param| match param {
+            syn::GenericParam::Type(type_param) => Some(type_param.ident.clone()),
+            syn::GenericParam::Const(const_param) => Some(const_param.ident.clone()),
+            syn::GenericParam::Lifetime(_) => None,
+        })
+        .collect();
+
+    let has_impl_trait_arg = sig.inputs.iter().any(|arg| match arg {
+        syn::FnArg::Typed(pat_type) => contains_impl_trait(quote!(#pat_type).into_iter()),
+        syn::FnArg::Receiver(_) => false,
     });
 
-    // Generate return value capture if requested
-    let ret_capture = args
-        .ret
-        .then_some(quote! {
-            if let Ok(ref ret_val) = result {
-                ::opentelemetry::trace::get_active_span(|span| {
-                    span.set_attribute(
-                        ::opentelemetry::KeyValue::new("return", format!("{:?}", ret_val))
-                    );
-                });
+    if generic_args.is_empty() || has_impl_trait_arg {
+        return proc_macro2::TokenStream::new();
+    }
+    quote! { ::<#(#generic_args),*> }
+}
+
+/// Drop binding modifiers and `&` patterns from a parameter pattern, so it can match
+/// a borrow of the argument, which doesn't allow them.
+fn strip_binding_modes(pat: &mut syn::Pat) {
+    match pat {
+        syn::Pat::Ident(ident) => {
+            ident.by_ref = None;
+            ident.mutability = None;
+            if let Some((_, subpat)) = &mut ident.subpat {
+                strip_binding_modes(subpat);
+            }
+        }
+        syn::Pat::Reference(reference) => {
+            let mut inner = reference.pat.as_ref().clone();
+            strip_binding_modes(&mut inner);
+            *pat = inner;
+        }
+        syn::Pat::TupleStruct(tuple_struct) => {
+            tuple_struct.elems.iter_mut().for_each(strip_binding_modes);
+        }
+        syn::Pat::Tuple(tuple) => tuple.elems.iter_mut().for_each(strip_binding_modes),
+        syn::Pat::Struct(struct_pat) => {
+            for field in &mut struct_pat.fields {
+                strip_binding_modes(&mut field.pat);
+            }
+        }
+        syn::Pat::Slice(slice) => slice.elems.iter_mut().for_each(strip_binding_modes),
+        syn::Pat::Paren(paren) => strip_binding_modes(&mut paren.pat),
+        _ => {}
+    }
+}
+
+/// The original function, kept under a hidden `<name>original` name, and how the
+/// instrumented wrapper calls it.
+struct Original {
+    item: ItemFn,
+    /// Names bound by destructured parameters, borrowed from the wrapper's arguments
+    /// so they can be recorded before the call.
+    bindings: proc_macro2::TokenStream,
+    call: proc_macro2::TokenStream,
+}
+
+/// Split the original function off the wrapper, which keeps the signature and gets
+/// the instrumented body. The original keeps the body, preceded by `prelude`. The
+/// wrapper's parameters are reduced to plain names, so they can be passed along.
+///
+/// `dispatch` is the path the original is called through when the function is known
+/// to be in an `impl` block, like `Self`. Otherwise methods and functions referring
+/// to `Self` outside of nested items are called through `Self`, since a sibling
+/// associated function can't be called by its bare name.
+fn split_original(
+    input_fn: &mut ItemFn,
+    dispatch: Option<&proc_macro2::TokenStream>,
+    prelude: proc_macro2::TokenStream,
+) -> syn::Result<Original> {
+    let mut item = input_fn.clone();
+    item.sig.ident = quote::format_ident!(
+        "{}original",
+        input_fn.sig.ident,
+        span = input_fn.sig.ident.span()
+    );
+    // Doc comments belong to the caller-facing wrapper only, the original is hidden
+    item.attrs.retain(|attr| !attr.path().is_ident("doc"));
+    item.attrs.insert(0, syn::parse_quote!(#[doc(hidden)]));
+    let stmts = &item.block.stmts;
+    item.block = syn::parse2(quote!({ #prelude #(#stmts)* }))?;
+
+    let mut args = Vec::new();
+    let mut bindings = proc_macro2::TokenStream::new();
+    let (sig, block) = (&input_fn.sig, &input_fn.block);
+    let mut dispatch_self = contains_ident(quote!(#sig), "Self") || body_refers_to_self(block);
+    for (i, arg) in input_fn.sig.inputs.iter_mut().enumerate() {
+        match arg {
+            syn::FnArg::Receiver(receiver) => {
+                // The wrapper only passes `self` along, so it doesn't need to be mutable
+                if receiver.reference.is_none() {
+                    receiver.mutability = None;
+                }
+                dispatch_self = true;
+                args.push(quote!(self));
+            }
+            syn::FnArg::Typed(pat_type) => match pat_type.pat.as_mut() {
+                syn::Pat::Ident(pat_ident)
+                    if pat_ident.by_ref.is_none() && pat_ident.subpat.is_none() =>
+                {
+                    pat_ident.mutability = None;
+                    let ident = &pat_ident.ident;
+                    args.push(quote!(#ident));
+                }
+                pat => {
+                    // Destructured parameters are passed whole, and their names bound
+                    // to borrows of it
+                    let arg = quote::format_ident!("__otel_arg{}", i);
+                    if !matches!(pat, syn::Pat::Wild(_)) {
+                        let mut pattern = pat.clone();
+                        strip_binding_modes(&mut pattern);
+                        bindings.extend(quote! {
+                            #[allow(unused_variables)]
+                            let #pattern = &#arg;
+                        });
+                    }
+                    *pat = syn::parse_quote!(#arg);
+                    args.push(quote!(#arg));
+                }
+            },
+        }
+    }
+
+    let original_ident = &item.sig.ident;
+    let turbofish = generic_turbofish(&item.sig);
+    let mut call = match dispatch {
+        Some(dispatch) => quote!(#dispatch::#original_ident #turbofish(#(#args),*)),
+        None if dispatch_self => quote!(Self::#original_ident #turbofish(#(#args),*)),
+        None => quote!(#original_ident #turbofish(#(#args),*)),
+    };
+    // The caller of the unsafe wrapper upholds the original's safety contract
+    if item.sig.unsafety.is_some() {
+        call = quote! { unsafe { #call } };
+    }
+    if item.sig.asyncness.is_some() {
+        call = quote!(#call.await);
+    }
+
+    Ok(Original {
+        item,
+        bindings,
+        call,
+    })
+}
+
+/// Check if a token stream contains `impl Trait` syntax anywhere.
+fn contains_impl_trait(tokens: proc_macro2::token_stream::IntoIter) -> bool {
+    tokens.into_iter().any(|token| match token {
+        proc_macro2::TokenTree::Ident(ident) => ident == "impl",
+        proc_macro2::TokenTree::Group(group) => contains_impl_trait(group.stream().into_iter()),
+        _ => false,
+    })
+}
+
+/// Instrument a function, returning the hidden original function along with the
+/// instrumented wrapper calling it. `dispatch` is the path the original is called
+/// through when the function is known to be in an `impl` block, for
+/// `#[instrument_impl]`.
+fn instrument_fn(
+    mut args: InstrumentArgs,
+    mut input_fn: ItemFn,
+    dispatch: Option<&proc_macro2::TokenStream>,
+) -> Result<(ItemFn, ItemFn), syn::Error> {
+    let fn_name = &input_fn.sig.ident;
+    let fn_name_str = fn_name.to_string();
+    // `module_path!` expands in the instrumented crate, qualifying the name with the
+    // module the function is defined in
+    let span_name = match (&args.name, &args.name_expr) {
+        (_, Some(name_expr)) => quote! {{
+            let __otel_name: ::std::borrow::Cow<'static, str> =
+                ::core::convert::Into::into(#name_expr);
+            __otel_name
+        }},
+        (Some(name), _) => quote!(#name),
+        (None, _) if args.qualified_name => quote!(concat!(module_path!(), "::", #fn_name_str)),
+        (None, _) => quote!(#fn_name_str),
+    };
+
+    // Check if function is async
+    let is_async = input_fn.sig.asyncness.is_some();
+    if args.task_local && !is_async {
+        return Err(syn::Error::new_spanned(
+            &input_fn.sig,
+            "`task_local` is only supported on async functions",
+        ));
+    }
+    if args.context_scoped.is_some() && (is_async || args.stream) {
+        return Err(syn::Error::new_spanned(
+            &input_fn.sig,
+            "`context_scoped` is only supported on non-async functions without `stream`",
+        ));
+    }
+    if args.gate.is_some() && !is_async {
+        return Err(syn::Error::new_spanned(
+            &input_fn.sig,
+            "`gate` is only supported on async functions",
+        ));
+    }
+    if args.task_id && !is_async {
+        return Err(syn::Error::new_spanned(
+            &input_fn.sig,
+            "`task_id` is only supported on async functions",
+        ));
+    }
+    if args.stream
+        && (is_async
+            || args.ret
+            || args.err.is_some()
+            || args.slo_ms.is_some()
+            || args.latency_buckets.is_some()
+            || args.record_ok)
+    {
+        return Err(syn::Error::new_spanned(
+            &input_fn.sig,
+            "`stream` is only supported on non-async functions without `ret`, `err`, `slo_ms`, `latency_bucket` or `record_ok`",
+        ));
+    }
+
+    // The mirrored `tracing` span is entered on every poll by a wrapper implementing
+    // the same `Stream` trait as the return type
+    let stream_trait = stream_trait(&input_fn.sig.output);
+    if cfg!(feature = "tracing-bridge") && args.stream && stream_trait.is_none() {
+        return Err(syn::Error::new_spanned(
+            &input_fn.sig.output,
+            "`stream` needs an `impl Stream` return type with the `tracing-bridge` feature",
+        ));
+    }
+
+    // The body can set the status itself with `set_span_status!`
+    let block = &input_fn.block;
+    let uses_status = invokes_macro(quote!(#block), "set_span_status");
+
+    // Deferred spans don't exist while the body runs, so nothing can depend on them
+    if args.min_duration_ms.is_some()
+        && (args.stream
+            || args.task_local
+            || args.context_scoped.is_some()
+            || args.expose_ids.is_some()
+            || args.test_ids
+            || args.request_id.is_some()
+            || args.start_time.is_some()
+            || args.gate.is_some()
+            || uses_status)
+    {
+        return Err(syn::Error::new_spanned(
+            &input_fn.sig,
+            "`min_duration_ms` can't be combined with `stream`, `task_local`, `context_scoped`, `expose_ids`, `test_ids`, `request_id`, `start_time`, `gate` or `set_span_status!`",
+        ));
+    }
+
+    // Macros rewriting async functions into ones returning a boxed future must run
+    // after `#[instrument]`, which needs to see the original `async fn`
+    if !is_async && !args.stream && returns_boxed_future(&input_fn.sig.output) {
+        return Err(syn::Error::new_spanned(
+            &input_fn.sig.output,
+            "`#[instrument]` can't instrument a boxed future, place it above macros rewriting async functions, like `#[async_recursion]`",
+        ));
+    }
+
+    // Hand-written `poll` functions have no error, and `Pending` is a normal outcome
+    let is_poll = returns_poll(&input_fn.sig.output);
+    if is_poll && (args.ret || args.err.is_some() || args.record_ok || args.stream) {
+        return Err(syn::Error::new_spanned(
+            &input_fn.sig.output,
+            "`ret`, `err`, `record_ok` and `stream` aren't supported on functions returning `Poll`",
+        ));
+    }
+
+    if args.metric_only {
+        return metric_only_fn(&args, input_fn, dispatch, span_name, is_poll);
+    }
+
+    // Fields referring to the `outcome` can only be recorded once the function has
+    // returned, so they are split from the fields recorded up front
+    let (outcome_fields, fields) = std::mem::take(&mut args.fields)
+        .into_iter()
+        .partition::<Vec<_>, _>(|(_, value)| value.uses_outcome());
+    args.fields = fields;
+    if args.stream && !outcome_fields.is_empty() {
+        return Err(syn::Error::new_spanned(
+            &input_fn.sig,
+            "`outcome` can't be used in `fields` with `stream`",
+        ));
+    }
+    // A parameter named `outcome` would be shadowed by the result in post-call fields
+    if !outcome_fields.is_empty()
+        && let Some(param) = input_fn.sig.inputs.iter().find_map(|arg| match arg {
+            syn::FnArg::Typed(pat_type) => match pat_type.pat.as_ref() {
+                syn::Pat::Ident(pat_ident) if pat_ident.ident == "outcome" => Some(pat_ident),
+                _ => None,
+            },
+            syn::FnArg::Receiver(_) => None,
+        })
+    {
+        return Err(syn::Error::new_spanned(
+            param,
+            "`outcome` in `fields` refers to the function's result, rename this parameter to use post-call fields",
+        ));
+    }
+
+    // Extract function parameters for span attributes
+    let mut param_names = Vec::new();
+    for arg in &input_fn.sig.inputs {
+        if let syn::FnArg::Typed(pat_type) = arg {
+            extract_idents_from_pattern(&pat_type.pat, &mut param_names);
+        }
+    }
+
+    // The out-param for `expose_ids` is only populated once the span exists, so
+    // recording it as an attribute would just show its initial value
+    if let Some(Expr::Path(out)) = &args.expose_ids
+        && let Some(ident) = out.path.get_ident()
+    {
+        args.skip.insert(ident.to_string());
+    }
+
+    // Generate span attributes from parameters (respecting skip and skip_all)
+    let span_param_names: Vec<_> = if args.skip_all {
+        Vec::new()
+    } else {
+        param_names
+            .iter()
+            .filter(|name| !args.skip.contains(&name.to_string()))
+            .collect()
+    };
+    // Namespace parameter and field keys with the span name if requested, using the
+    // bare function name when the span name is qualified at compile time
+    let namespace = args
+        .namespace_attrs
+        .then(|| args.name.clone().unwrap_or_else(|| fn_name_str.clone()));
+    let attr_key = |key: &str| match &namespace {
+        Some(namespace) => format!("{namespace}.{key}"),
+        None => key.to_string(),
+    };
+    let span_attrs: Vec<_> = span_param_names
+        .iter()
+        .map(|name| {
+            let name_str = attr_key(&name.to_string());
+            if args.strict {
+                let value = strict_attribute_value(name);
+                return quote! {
+                    if let Some(__otel_value) = #value {
+                        __otel_span.set_attribute(::opentelemetry::KeyValue::new(#name_str, __otel_value));
+                    }
+                };
+            }
+            let value = attribute_value(name);
+            quote! {
+                __otel_span.set_attribute(::opentelemetry::KeyValue::new(#name_str, #value));
             }
         })
-        .unwrap_or_default();
+        .collect();
 
-    // Generate error capture if requested (enhanced version)
-    let err_capture = if let Some(err_expr) = &args.err {
+    // Generate custom field attributes, wrapping conditional ones in `#[cfg]` blocks
+    let field_attrs = args
+        .fields
+        .iter()
+        .map(|(name, value)| field_attr(&attr_key(name), value));
+    let cfg_field_attrs = args.cfg_fields.iter().map(|(predicate, fields)| {
+        let attrs = fields
+            .iter()
+            .map(|(name, value)| field_attr(&attr_key(name), value));
         quote! {
-            match &result {
-                Ok(_) => {
-                    ::opentelemetry::trace::get_active_span(|span| {
-                        span.set_status(::opentelemetry::trace::Status::Ok);
+            #[cfg(#predicate)]
+            {
+                #(#attrs)*
+            }
+        }
+    });
+
+    // Generate the post-call fields, with the `outcome` bound to the function's result
+    let outcome_capture = if outcome_fields.is_empty() {
+        proc_macro2::TokenStream::new()
+    } else {
+        let attrs = outcome_fields
+            .iter()
+            .map(|(name, value)| field_attr(&attr_key(name), value));
+        quote! {
+            ::opentelemetry::trace::get_active_span(|__otel_span| {
+                let outcome = &__otel_result;
+                #(#attrs)*
+            });
+        }
+    };
+
+    // Generate return value capture if requested. Recording `()` for a
+    // `Result<(), E>` is just noise, so unit success values are skipped. Only
+    // attributes are recorded here, the status is left to the error capture, so
+    // it is set exactly once per outcome.
+    let ret_capture = if !args.ret_projections.is_empty()
+        || args.ret_variant
+        || args.ret_len.is_some()
+    {
+        let variant = args.ret_variant.then_some(quote! {
+            // `variant_name` is generated by `#[derive(VariantName)]`
+            __otel_span.set_attribute(::opentelemetry::KeyValue::new("return", __otel_ret.variant_name()));
+        });
+        // Spanned to `len`, so a success value without a `len` method is reported there
+        let len = args.ret_len.as_ref().map(|len| {
+            quote::quote_spanned! {len.span()=>
+                __otel_span.set_attribute(::opentelemetry::KeyValue::new("return.len", (&__otel_ret).len() as i64));
+            }
+        });
+        let projections = args.ret_projections.iter().map(|(key, expr)| {
+            let value = attribute_value(expr);
+            quote! {
+                __otel_span.set_attribute(::opentelemetry::KeyValue::new(#key, #value));
+            }
+        });
+        let projections = variant.into_iter().chain(len).chain(projections);
+        quote! {
+            if let Ok(ref __otel_ret) = __otel_result {
+                ::opentelemetry::trace::get_active_span(|__otel_span| {
+                    #(#projections)*
+                });
+            }
+        }
+    } else {
+        let format = match ret_format(&input_fn.sig.output, args.ret_display) {
+            RetFormat::Debug => Some(quote!("{:?}")),
+            RetFormat::Display => Some(quote!("{}")),
+            RetFormat::Opaque(span) => {
+                args.deprecations.push((
+                    span,
+                    "`ret` can't format an `impl Trait` without a `Debug` or `Display` bound, so the success value isn't recorded",
+                ));
+                None
+            }
+        };
+        let returns_value = !returns_unit_result(&input_fn.sig.output);
+        match (args.ret_compact, format) {
+            (Some(limit), format) => {
+                let value = format.filter(|_| returns_value).map(|format| {
+                    quote! {
+                        if let Ok(ref __otel_ret) = __otel_result {
+                            let __otel_value = format!(#format, __otel_ret);
+                            if __otel_value.len() <= #limit {
+                                __otel_span.set_attribute(::opentelemetry::KeyValue::new("return.value", __otel_value));
+                            }
+                        }
+                    }
+                });
+                quote! {
+                    ::opentelemetry::trace::get_active_span(|__otel_span| {
+                        __otel_span.set_attribute(::opentelemetry::KeyValue::new("return.ok", __otel_result.is_ok()));
+                        #value
                     });
                 }
-                Err(e) => {
-                    ::opentelemetry::trace::get_active_span(|span| {
-                        span.set_attribute(::opentelemetry::KeyValue::new("error", format!("{:?}", e)));
-                        span.set_status(::opentelemetry::trace::Status::error(format!("{:?}", e)));
-                        let err = #err_expr;
-                        span.record_error(err);
+            }
+            (None, Some(format)) if args.ret && returns_value => quote! {
+                if let Ok(ref __otel_ret) = __otel_result {
+                    ::opentelemetry::trace::get_active_span(|__otel_span| {
+                        __otel_span.set_attribute(
+                            ::opentelemetry::KeyValue::new("return", format!(#format, __otel_ret))
+                        );
                     });
                 }
+            },
+            _ => proc_macro2::TokenStream::new(),
+        }
+    };
+
+    // Generate error capture if requested (enhanced version). A `Box<dyn Error>`
+    // doesn't implement `Error` itself, so record the boxed error by default.
+    // Spelling out `e.as_ref()` was needed before it was the default, and is now the
+    // legacy form for these functions
+    if let Some(err) = &mut args.err
+        && returns_boxed_error(&input_fn.sig.output)
+    {
+        let default: Expr = syn::parse_quote!(e.as_ref());
+        match &err.record {
+            None => err.record = Some(default),
+            Some(record) if quote!(#record).to_string() == quote!(#default).to_string() => {
+                args.deprecations.push((
+                    syn::spanned::Spanned::span(record),
+                    "`err = e.as_ref()` is the default for `Box<dyn Error>` results, use `err` instead",
+                ));
             }
+            Some(_) => {}
+        }
+    }
+    let err_capture = if args.manual_status && args.err.is_none() {
+        // The span status is managed by the function itself
+        proc_macro2::TokenStream::new()
+    } else if is_poll {
+        // Record whether the poll completed, leaving the status unset
+        quote! {
+            ::opentelemetry::trace::get_active_span(|__otel_span| {
+                __otel_span.set_attribute(::opentelemetry::KeyValue::new("poll.ready", __otel_result.is_ready()));
+            });
+        }
+    } else {
+        // The body can set the status itself with `set_span_status!`, which tracks
+        // whether it did
+        // With `status = manual`, errors are still recorded, only the status is skipped
+        let mode = if args.manual_status {
+            StatusMode::Manual
+        } else if uses_status {
+            StatusMode::Overridable
+        } else {
+            StatusMode::Auto
+        };
+        status_capture(args.err.as_ref(), mode)
+    };
+    // Generate the outcome attribute if requested, for backends that can't filter
+    // on the span status
+    let err_capture = if args.record_ok {
+        quote! {
+            #err_capture
+            ::opentelemetry::trace::get_active_span(|__otel_span| {
+                __otel_span.set_attribute(::opentelemetry::KeyValue::new("ok", __otel_result.is_ok()));
+            });
+        }
+    } else {
+        err_capture
+    };
+
+    // Generate the parent context lookup based on whether parent is specified. Root
+    // spans ignore the ambient context and start a new trace.
+    let parent_ctx = if args.root {
+        quote! { let __otel_parent_ctx = ::opentelemetry::Context::new(); }
+    } else if let Some(parent_expr) = &args.parent {
+        // Spanned to the expression, so a type that can't be used as a parent is
+        // reported there instead of on the whole attribute
+        quote::quote_spanned! {syn::spanned::Spanned::span(parent_expr)=>
+            let __otel_parent_ctx = _OTEL_TRACER_NAME::into_parent_context(&(#parent_expr));
+        }
+    } else if args.task_local {
+        quote! {
+            // Fall back to the task-local context when the thread-local one has no
+            // active span, for example when running inside a freshly spawned task.
+            let __otel_parent_ctx = {
+                use ::opentelemetry::trace::TraceContextExt as _;
+                let __otel_current_ctx = ::opentelemetry::Context::current();
+                if __otel_current_ctx.has_active_span() {
+                    __otel_current_ctx
+                } else {
+                    _OTEL_TASK_CONTEXT
+                        .try_with(|ctx| ctx.clone())
+                        .unwrap_or(__otel_current_ctx)
+                }
+            };
         }
     } else {
+        quote! { let __otel_parent_ctx = ::opentelemetry::Context::current(); }
+    };
+
+    // Generate span creation code, backdating the start time if requested
+    let start_time = args
+        .start_time
+        .as_ref()
+        .map(|start_time| quote! { .with_start_time(#start_time) });
+    let span_creation = quote! {
+        #parent_ctx
+        let mut __otel_span = __otel_tracer
+            .span_builder(#span_name)
+            #start_time
+            .start_with_context(__otel_tracer, &__otel_parent_ctx);
+    };
+
+    // Generate package attributes if requested. `env!` expands in the instrumented
+    // crate, so these resolve to its own package name and version.
+    let package_attrs = args
+        .package_info
+        .then_some(quote! {
+            __otel_span.set_attribute(::opentelemetry::KeyValue::new("code.package", env!("CARGO_PKG_NAME")));
+            __otel_span.set_attribute(::opentelemetry::KeyValue::new("code.package.version", env!("CARGO_PKG_VERSION")));
+        })
+        .unwrap_or_default();
+
+    // Generate the parent check if requested. This looks at the context the span is
+    // started in, so it covers ambient, explicit and task-local parents alike.
+    let has_parent_attr = args
+        .has_parent
+        .then_some(quote! {
+            __otel_span.set_attribute(::opentelemetry::KeyValue::new(
+                "has_parent",
+                ::opentelemetry::trace::TraceContextExt::span(&__otel_parent_ctx)
+                    .span_context()
+                    .is_valid(),
+            ));
+        })
+        .unwrap_or_default();
+
+    // Generate baggage attributes if requested, read from the context the span is
+    // started in. Keys missing from the baggage are omitted.
+    let baggage_attrs = if args.baggage_attrs.is_empty() {
+        proc_macro2::TokenStream::new()
+    } else {
+        let keys = &args.baggage_attrs;
         quote! {
-            if let Ok(_) = result {
-               ::opentelemetry::trace::get_active_span(|span| {
-                   span.set_status(::opentelemetry::trace::Status::Ok);
-               });
+            {
+                use ::opentelemetry::baggage::BaggageExt as _;
+                let __otel_baggage = __otel_parent_ctx.baggage();
+                for __otel_key in [#(#keys),*] {
+                    if let Some(__otel_value) = __otel_baggage.get(__otel_key) {
+                        __otel_span.set_attribute(::opentelemetry::KeyValue::new(__otel_key, __otel_value.clone()));
+                    }
+                }
             }
         }
     };
 
-    // Generate span creation code based on whether parent is specified
-    let span_creation = if let Some(parent_expr) = &args.parent {
+    // Generate the id attributes if requested, to assert against in tests
+    let test_ids = args
+        .test_ids
+        .then_some(quote! {
+            let __otel_span_context = __otel_span.span_context().clone();
+            __otel_span.set_attribute(::opentelemetry::KeyValue::new("trace_id", __otel_span_context.trace_id().to_string()));
+            __otel_span.set_attribute(::opentelemetry::KeyValue::new("span_id", __otel_span_context.span_id().to_string()));
+        })
+        .unwrap_or_default();
+
+    // Generate the span context out-param population if requested
+    let expose_ids = args
+        .expose_ids
+        .as_ref()
+        .map(|out| {
+            quote! {
+                *(#out) = ::core::convert::From::from(__otel_span.span_context().clone());
+            }
+        })
+        .unwrap_or_default();
+
+    // Generate the async marker attribute if requested
+    let async_attr = args
+        .record_async
+        .then_some(quote! {
+            __otel_span.set_attribute(::opentelemetry::KeyValue::new("code.async", #is_async));
+        })
+        .unwrap_or_default();
+
+    // Generate the visibility attribute if requested, known at compile time
+    let visibility_attr = if args.record_visibility {
+        let visibility = match &input_fn.vis {
+            syn::Visibility::Public(_) => "pub".to_string(),
+            syn::Visibility::Restricted(restricted) => {
+                let path = &restricted.path;
+                let path = quote!(#path).to_string().replace(' ', "");
+                match restricted.in_token {
+                    Some(_) => format!("pub(in {path})"),
+                    None => format!("pub({path})"),
+                }
+            }
+            syn::Visibility::Inherited => "private".to_string(),
+        };
         quote! {
-            use ::opentelemetry::Context;
-            // The parent_value should implement Into<Context> or be a Context
-            // This allows for flexibility in what users can pass:
-            // - Context directly
-            // - Span (which can be converted to Context)
-            // - SpanContext (which can be used to create Context)
-            let parent_ctx = #parent_expr.clone().into();
-            let mut span = tracer.start_with_context(#span_name, &parent_ctx);
+            __otel_span.set_attribute(::opentelemetry::KeyValue::new("code.visibility", #visibility));
         }
     } else {
-        quote! { let mut span = tracer.start(#span_name); }
+        proc_macro2::TokenStream::new()
     };
 
-    let mut original_fn = input_fn.clone();
-    original_fn.sig.ident = syn::Ident::new(
-        &(input_fn.sig.ident.to_string() + "original"),
-        input_fn.sig.span(),
-    );
-    let original_ident = original_fn.sig.ident.clone();
-    let call = if let Some(ident) = self_ident {
+    // Generate the parameter list attribute if requested. Skipped parameters are
+    // listed too, as only their values are sensitive.
+    let param_names_attr = if args.param_names {
+        let names = param_names
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(",");
         quote! {
-            #ident.#original_ident(#(#param_patterns),*)
+            __otel_span.set_attribute(::opentelemetry::KeyValue::new("code.parameters", #names));
         }
     } else {
+        proc_macro2::TokenStream::new()
+    };
+
+    // Generate the tokio task id attribute if requested, omitted outside of a task
+    let task_id_attr = args
+        .task_id
+        .then_some(quote! {
+            if let Some(id) = ::tokio::task::try_id() {
+                __otel_span.set_attribute(::opentelemetry::KeyValue::new("tokio.task.id", id.to_string()));
+            }
+        })
+        .unwrap_or_default();
+
+    // Generate build provenance attributes if requested, from the constants defined
+    // by `tracer_name!`
+    let build_attrs = args
+        .build_info
+        .then_some(quote! {
+            if let Some(commit) = _OTEL_TRACER_NAME::_OTEL_BUILD_COMMIT {
+                __otel_span.set_attribute(::opentelemetry::KeyValue::new("build.commit", commit));
+            }
+            if let Some(time) = _OTEL_TRACER_NAME::_OTEL_BUILD_TIME {
+                __otel_span.set_attribute(::opentelemetry::KeyValue::new("build.time", time));
+            }
+        })
+        .unwrap_or_default();
+
+    // Generate caller span id capture if requested. This reads the span that is
+    // active when the function is called, before our own span becomes active.
+    let (parent_id_capture, parent_id_attr) = if args.record_parent_id {
+        (
+            quote! {
+                let __otel_parent_span_id = {
+                    use ::opentelemetry::trace::TraceContextExt as _;
+                    let __otel_current_ctx = ::opentelemetry::Context::current();
+                    let __otel_span_ctx = __otel_current_ctx.span().span_context().clone();
+                    __otel_span_ctx.is_valid().then(|| __otel_span_ctx.span_id().to_string())
+                };
+            },
+            quote! {
+                if let Some(__otel_parent_span_id) = __otel_parent_span_id {
+                    __otel_span.set_attribute(::opentelemetry::KeyValue::new("parent.span_id", __otel_parent_span_id));
+                }
+            },
+        )
+    } else {
+        Default::default()
+    };
+
+    // Generate request id capture if requested. The id is recorded on the span and
+    // added to the baggage of the span's context, so it propagates to callees.
+    let (request_id_capture, request_id_attr, request_id_baggage) =
+        if let Some(request_id) = &args.request_id {
+            (
+                quote! {
+                    let __otel_request_id = ::std::string::ToString::to_string(&(#request_id));
+                },
+                quote! {
+                    __otel_span.set_attribute(::opentelemetry::KeyValue::new("request.id", __otel_request_id.clone()));
+                },
+                quote! {
+                    let __otel_ctx = {
+                        use ::opentelemetry::baggage::{BaggageExt as _, KeyValueMetadata};
+                        // `with_baggage` replaces the baggage, so existing entries are carried over
+                        let __otel_baggage = __otel_ctx
+                            .baggage()
+                            .iter()
+                            .map(|(key, (value, metadata))| {
+                                KeyValueMetadata::new(key.clone(), value.clone(), metadata.clone())
+                            })
+                            .chain([::opentelemetry::KeyValue::new("request.id", __otel_request_id).into()])
+                            .collect::<Vec<_>>();
+                        __otel_ctx.with_baggage(__otel_baggage)
+                    };
+                },
+            )
+        } else {
+            Default::default()
+        };
+
+    // Track the previous checkpoint only if the body uses `checkpoint!`, which reads
+    // and updates it. It is declared at the start of the original body, which is
+    // where the macro is used.
+    let block = &input_fn.block;
+    let checkpoint_start = invokes_macro(quote!(#block), "checkpoint")
+        .then_some(quote! {
+            let __otel_checkpoint = ::std::cell::Cell::new(::std::time::Instant::now());
+        })
+        .unwrap_or_default();
+    // The span's context is bound for the body if requested, so it can be passed to
+    // spawned threads, which don't inherit the thread-local context. The original body
+    // runs with the span's context attached, so it is the current one.
+    let context_binding = args.context_scoped.as_ref().map(|name| {
         quote! {
-            #original_ident(#(#param_patterns),*)
+            #[allow(unused_variables)]
+            let #name = ::opentelemetry::Context::current();
         }
+    });
+    // Track whether the body set the status only if it uses `set_span_status!`. The
+    // flag is shared with the body through the span's context, and atomic so async
+    // functions stay `Send`.
+    let status_set_start = uses_status
+        .then_some(quote! {
+            let __otel_status_set =
+                ::std::sync::Arc::new(::std::sync::atomic::AtomicBool::new(false));
+        })
+        .unwrap_or_default();
+    let status_set_ctx = uses_status
+        .then_some(quote! {
+            let __otel_ctx = __otel_ctx.with_value(_OTEL_TRACER_NAME::_OtelStatusSet(__otel_status_set.clone()));
+        })
+        .unwrap_or_default();
+
+    let Original {
+        item: original,
+        bindings,
+        call,
+    } = split_original(
+        &mut input_fn,
+        dispatch,
+        quote! {
+            #checkpoint_start
+            #context_binding
+        },
+    )?;
+
+    // Time the original body only, if the SLO check or latency bucket need it
+    let timer_start = (args.slo_ms.is_some() || args.latency_buckets.is_some())
+        .then_some(quote! { let __otel_start = ::std::time::Instant::now(); })
+        .unwrap_or_default();
+
+    // Generate the gate acquisition if requested. The wait is recorded separately,
+    // so it doesn't count towards the body's timing, and the permit is held until
+    // the body has returned.
+    let gate = args
+        .gate
+        .as_ref()
+        .map(|gate| {
+            quote! {
+                let __otel_wait_start = ::std::time::Instant::now();
+                let __otel_permit = (#gate).await;
+                let __otel_wait_ms = __otel_wait_start.elapsed().as_secs_f64() * 1000.0;
+                ::opentelemetry::trace::get_active_span(|__otel_span| {
+                    __otel_span.set_attribute(::opentelemetry::KeyValue::new("wait_ms", __otel_wait_ms));
+                });
+            }
+        })
+        .unwrap_or_default();
+
+    // Generate the SLO check if requested
+    let slo_check = args
+        .slo_ms
+        .as_ref()
+        .map(|slo_ms| {
+            quote! {
+                let __otel_slo_exceeded = __otel_start.elapsed()
+                    > ::std::time::Duration::from_millis(#slo_ms);
+                ::opentelemetry::trace::get_active_span(|__otel_span| {
+                    __otel_span.set_attribute(::opentelemetry::KeyValue::new("slo.exceeded", __otel_slo_exceeded));
+                });
+            }
+        })
+        .unwrap_or_default();
+
+    // Generate the latency bucket if requested. Labels are built at compile time, so
+    // only the matching one is looked up at runtime.
+    let latency_bucket = args
+        .latency_buckets
+        .as_ref()
+        .map(|bounds| {
+            let labels = bounds.iter().enumerate().map(|(i, bound)| match i {
+                0 => format!("<{bound}ms"),
+                _ => format!("{}-{bound}ms", bounds[i - 1]),
+            });
+            let overflow = format!(">={}ms", bounds[bounds.len() - 1]);
+            quote! {
+                let __otel_bucket = _OTEL_TRACER_NAME::_otel_latency_bucket(
+                    __otel_start.elapsed(),
+                    &[#((#bounds, #labels)),*],
+                    #overflow,
+                );
+                ::opentelemetry::trace::get_active_span(|__otel_span| {
+                    __otel_span.set_attribute(::opentelemetry::KeyValue::new("latency.bucket", __otel_bucket));
+                });
+            }
+        })
+        .unwrap_or_default();
+    let slo_check = quote! {
+        #slo_check
+        #latency_bucket
+    };
+
+    // Generate the context the original body runs in
+    let otel_ctx = quote! {
+        let __otel_ctx = <::opentelemetry::Context as ::opentelemetry::trace::TraceContextExt>::current_with_span(__otel_span);
+        #request_id_baggage
+        #status_set_ctx
+    };
+
+    // Generate a mirrored `tracing` span with the same name and parameter fields if the
+    // bridge is enabled, so `tracing` based tooling keeps working during a migration.
+    // Only plain fields are mirrored, as the helpers record extra attributes.
+    let (tracing_span, tracing_enter) = if cfg!(feature = "tracing-bridge") {
+        // `tracing` span names must be constant, so computed names use the function name
+        let tracing_name = if args.name_expr.is_some() {
+            quote!(#fn_name_str)
+        } else {
+            span_name.clone()
+        };
+        let param_fields = span_param_names.iter().map(|name| {
+            let name_str = name.to_string();
+            if args.strict {
+                // Skipped parameters are left empty, which `tracing` doesn't record
+                let value = strict_attribute_value(name);
+                return quote!(#name_str = #value.map(|value| value.to_string()));
+            }
+            let value = attribute_value(name);
+            quote!(#name_str = %#value)
+        });
+        let custom_fields = args.fields.iter().filter_map(|(name, value)| match value {
+            FieldValue::Debug(expr) => {
+                let value = attribute_value(expr);
+                Some(quote!(#name = %#value))
+            }
+            _ => None,
+        });
+        (
+            quote! {
+                let __otel_tracing_span = ::tracing::info_span!(#tracing_name, #(#param_fields,)* #(#custom_fields,)*);
+            },
+            quote! {
+                let __otel_tracing_guard = __otel_tracing_span.enter();
+            },
+        )
+    } else {
+        Default::default()
     };
 
-    // Generate the result execution block based on whether function is async or sync
-    let result_block = if is_async {
+    // Generate the result execution block based on whether function is async, sync or
+    // returns a stream. Streams are wrapped so the span's context is attached on every
+    // poll, and the span ends when the stream is dropped.
+    let result_block = if args.stream {
+        let tracing_stream = stream_trait.filter(|_| !tracing_span.is_empty()).map(|stream_trait| {
+            quote! {
+                struct __OtelTracingStream<S> {
+                    stream: ::std::pin::Pin<Box<S>>,
+                    span: ::tracing::Span,
+                }
+                impl<S: #stream_trait> #stream_trait for __OtelTracingStream<S> {
+                    type Item = S::Item;
+                    fn poll_next(
+                        self: ::std::pin::Pin<&mut Self>,
+                        cx: &mut ::std::task::Context<'_>,
+                    ) -> ::std::task::Poll<Option<S::Item>> {
+                        let this = self.get_mut();
+                        let _enter = this.span.enter();
+                        #stream_trait::poll_next(this.stream.as_mut(), cx)
+                    }
+                    fn size_hint(&self) -> (usize, Option<usize>) {
+                        #stream_trait::size_hint(&*self.stream)
+                    }
+                }
+                let __otel_result = __OtelTracingStream {
+                    stream: Box::pin(__otel_result),
+                    span: __otel_tracing_span,
+                };
+            }
+        });
         quote! {
-            use ::opentelemetry::{context::FutureExt, trace::TraceContextExt};
-            let result = async move {
-                let result = #call.await;
+            #otel_ctx
+            let __otel_result = {
+                let __otel_guard = __otel_ctx.clone().attach();
+                #tracing_enter
+                #call
+            };
+            #tracing_stream
+            let __otel_result =
+                ::opentelemetry::context::FutureExt::with_context(__otel_result, __otel_ctx);
+        }
+    } else if is_async {
+        let future = quote! {
+            async move {
+                #gate
+                #timer_start
+                let __otel_result = #call;
+                #slo_check
                 #ret_capture
+                #outcome_capture
                 #err_capture
-                result
+                __otel_result
+            }
+        };
+        let future = if tracing_span.is_empty() {
+            future
+        } else {
+            quote!(::tracing::Instrument::instrument(#future, __otel_tracing_span))
+        };
+        if args.task_local {
+            quote! {
+                #otel_ctx
+                let __otel_result = _OTEL_TASK_CONTEXT
+                    .scope(
+                        __otel_ctx.clone(),
+                        ::opentelemetry::context::FutureExt::with_context(#future, __otel_ctx),
+                    )
+                    .await;
+            }
+        } else {
+            quote! {
+                #otel_ctx
+                let __otel_result =
+                    ::opentelemetry::context::FutureExt::with_context(#future, __otel_ctx).await;
             }
-            .with_context(::opentelemetry::Context::current_with_span(span))
-            .await;
         }
     } else {
         quote! {
-            let _guard = ::opentelemetry::trace::mark_span_as_active(span);
-            let result = #call;
+            #otel_ctx
+            let __otel_guard = __otel_ctx.attach();
+            #tracing_enter
+            #timer_start
+            let __otel_result = #call;
+            #slo_check
             #ret_capture
+            #outcome_capture
             #err_capture
         }
     };
 
-    // Create the instrumented function body
-    let instrumented_body = quote! {
-        {
-            use ::opentelemetry::{trace::{Tracer, Span}, global};
+    // Generate the provider shutdown if requested. The provider is created before the
+    // span so the span is recorded by it, and shut down by a guard declared first, so
+    // it drops after the span has ended, even when the body panics.
+    let root_shutdown = args
+        .root_provider
+        .as_ref()
+        .map(|provider| {
+            quote! {
+                struct __OtelShutdown<F: FnMut()>(F);
+                impl<F: FnMut()> Drop for __OtelShutdown<F> {
+                    fn drop(&mut self) {
+                        (self.0)()
+                    }
+                }
+                let __otel_provider = #provider;
+                let __otel_shutdown = __OtelShutdown(|| {
+                    let _ = __otel_provider.shutdown();
+                });
+            }
+        })
+        .unwrap_or_default();
+
+    let tracer = tracer_lookup(&args);
+    let deprecations = deprecation_warnings(&args);
+
+    // Deferred spans are started after the call, but the mirrored `tracing` span
+    // covers the body like without `min_duration_ms`
+    let deferred_call = if tracing_span.is_empty() {
+        call.clone()
+    } else if is_async {
+        quote!(::tracing::Instrument::instrument(async { #call }, __otel_tracing_span).await)
+    } else {
+        quote!({
+            #tracing_enter
+            #call
+        })
+    };
+
+    // Create the instrumented function body. Trait imports are scoped to the span
+    // setup, so they don't leak into the original body.
+    let instrumented_body = if let Some(min_duration_ms) = &args.min_duration_ms {
+        // The span is only started once the body has run longer than the threshold,
+        // backdated to when the call began, so fast calls never create one. Attributes
+        // are collected up front, while the parameters are still available.
+        quote! {
+            {
+                #deprecations
+                #root_shutdown
+                #bindings
+                let (__otel_name, __otel_parent_ctx, __otel_attributes) = {
+                    struct __OtelAttributes(Vec<::opentelemetry::KeyValue>);
+                    impl __OtelAttributes {
+                        fn set_attribute(&mut self, attribute: ::opentelemetry::KeyValue) {
+                            self.0.push(attribute);
+                        }
+                        fn is_recording(&self) -> bool {
+                            true
+                        }
+                    }
+
+                    #parent_id_capture
+                    #parent_ctx
+                    let mut __otel_span = __OtelAttributes(Vec::new());
+                    for __otel_field in _OTEL_TRACER_NAME::_otel_default_fields() {
+                        __otel_span.set_attribute(__otel_field);
+                    }
+                    #parent_id_attr
+                    #has_parent_attr
+                    #baggage_attrs
+                    #package_attrs
+                    #build_attrs
+                    #async_attr
+                    #visibility_attr
+                    #task_id_attr
+                    #param_names_attr
+                    #(#span_attrs)*
+                    #(#field_attrs)*
+                    #(#cfg_field_attrs)*
+                    (#span_name, __otel_parent_ctx, __otel_span.0)
+                };
+                let __otel_start_time = ::std::time::SystemTime::now();
+                let __otel_timer = ::std::time::Instant::now();
+                #timer_start
+                #tracing_span
+                let __otel_result = #deferred_call;
+                if __otel_timer.elapsed() >= ::std::time::Duration::from_millis(#min_duration_ms) {
+                    let __otel_span = {
+                        use ::opentelemetry::trace::Tracer as _;
+
+                        let __otel_tracer = #tracer;
+                        __otel_tracer
+                            .span_builder(__otel_name)
+                            .with_start_time(__otel_start_time)
+                            .with_attributes(__otel_attributes)
+                            .start_with_context(__otel_tracer, &__otel_parent_ctx)
+                    };
+                    let __otel_guard = <::opentelemetry::Context as ::opentelemetry::trace::TraceContextExt>::current_with_span(__otel_span).attach();
+                    #slo_check
+                    #ret_capture
+                    #outcome_capture
+                    #err_capture
+                }
+                __otel_result
+            }
+        }
+    } else {
+        quote! {
+            {
+                #deprecations
+                #root_shutdown
+                #bindings
+                #request_id_capture
+                let __otel_span = {
+                    use ::opentelemetry::trace::{Span as _, Tracer as _};
 
-            let tracer = global::tracer(_OTEL_TRACER_NAME);
-            #span_creation
-            #(#span_attrs)*
-            #(#field_attrs)*
-            #result_block
-            result
+                    let __otel_tracer = #tracer;
+                    #parent_id_capture
+                    #span_creation
+                    #expose_ids
+                    #test_ids
+                    for __otel_field in _OTEL_TRACER_NAME::_otel_default_fields() {
+                        __otel_span.set_attribute(__otel_field);
+                    }
+                    #parent_id_attr
+                    #has_parent_attr
+                    #baggage_attrs
+                    #package_attrs
+                    #build_attrs
+                    #async_attr
+                    #visibility_attr
+                    #task_id_attr
+                    #param_names_attr
+                    #request_id_attr
+                    #(#span_attrs)*
+                    #(#field_attrs)*
+                    #(#cfg_field_attrs)*
+                    __otel_span
+                };
+                #tracing_span
+                #status_set_start
+                #result_block
+                __otel_result
+            }
         }
     };
 
     // Replace the function body
     input_fn.block = syn::parse2(instrumented_body)?;
+    let mut original = original;
+    allow_consumed_params(&mut original, &input_fn.block, quote!(#bindings #call));
 
-    Ok(quote! {
-        #[doc(hidden)]
-        #original_fn
-        #input_fn
-    })
+    Ok((original, input_fn))
 }
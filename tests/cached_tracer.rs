@@ -0,0 +1,62 @@
+#![cfg(feature = "cached_tracer")]
+
+use opentelemetry::{Value, global};
+use opentelemetry_sdk::trace::{InMemorySpanExporter, SdkTracerProvider, SpanData};
+use otel_instrument::{instrument, tracer_name};
+
+tracer_name!(
+    "otel-instrument-cached-tests",
+    db = "otel-instrument-cached-tests.db"
+);
+
+fn attribute(span: &SpanData, key: &str) -> Option<Value> {
+    span.attributes
+        .iter()
+        .find(|kv| kv.key.as_str() == key)
+        .map(|kv| kv.value.clone())
+}
+
+#[instrument(ret)]
+fn cached_default(id: u32) -> Result<u32, String> {
+    Ok(id * 2)
+}
+
+#[instrument(tracer = db)]
+async fn cached_scope() -> Result<(), String> {
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_cached_tracer() {
+    let exporter = InMemorySpanExporter::default();
+    let tracer_provider = SdkTracerProvider::builder()
+        .with_simple_exporter(exporter.clone())
+        .build();
+    global::set_tracer_provider(tracer_provider);
+
+    cached_default(1).unwrap();
+    cached_default(2).unwrap();
+    cached_scope().await.unwrap();
+
+    // The tracer is looked up once per scope
+    assert!(std::ptr::eq(
+        _OTEL_TRACER_NAME::_otel_tracer(),
+        _OTEL_TRACER_NAME::_otel_tracer()
+    ));
+
+    let spans = exporter.get_finished_spans().unwrap();
+    let names: Vec<_> = spans
+        .iter()
+        .map(|span| (span.name.as_ref(), span.instrumentation_scope.name()))
+        .collect();
+    assert_eq!(
+        names,
+        [
+            ("cached_default", "otel-instrument-cached-tests"),
+            ("cached_default", "otel-instrument-cached-tests"),
+            ("cached_scope", "otel-instrument-cached-tests.db"),
+        ]
+    );
+    assert_eq!(attribute(&spans[1], "id"), Some(Value::I64(2)));
+    assert_eq!(attribute(&spans[1], "return"), Some(Value::from("4")));
+}
@@ -3,7 +3,7 @@ use opentelemetry::global;
 use opentelemetry::trace::TraceContextExt;
 use opentelemetry_otlp::SpanExporter;
 use opentelemetry_sdk::trace::{RandomIdGenerator, Sampler, SdkTracerProvider};
-use otel_instrument::{instrument, tracer_name};
+use otel_instrument::{instrument, instrument_impl, tracer_name};
 
 tracer_name!("otel-instrument-tests");
 
@@ -25,7 +25,7 @@ fn instrumented_struct_destructure(_Test { id }: _Test) -> Result<String> {
     Ok(format!("Processing id: {}", id))
 }
 
-// Test tuple struct destructuring patterns  
+// Test tuple struct destructuring patterns
 struct StateWrapper(String);
 
 #[instrument]
@@ -245,6 +245,198 @@ fn sync_test_parent_context_function(
     Ok(format!("Child span with param: {param}"))
 }
 
+// Test generic functions with multi-bound where clauses
+#[instrument(ret)]
+fn generic_where_convert<T, U>(value: T) -> Result<U>
+where
+    T: Into<U> + std::fmt::Debug + Clone,
+    U: std::fmt::Debug + Default + PartialEq,
+{
+    let converted: U = value.into();
+    if converted == U::default() {
+        bail!("Converted to default value")
+    }
+    Ok(converted)
+}
+
+// Test generics that can only be resolved through the forwarded turbofish
+#[instrument]
+fn generic_where_default<T, const N: usize>() -> Result<String>
+where
+    T: Default + std::fmt::Debug,
+{
+    Ok(format!("{:?}", [(); N].map(|_| T::default())))
+}
+
+impl _Test {
+    #[instrument(ret)]
+    async fn generic_where_method<T>(&self, value: T) -> Result<String>
+    where
+        T: std::fmt::Display + std::fmt::Debug + Send,
+    {
+        Ok(format!("{value} for {}", self.id))
+    }
+
+    // The type parameter only appears in the return type, so callers need a turbofish
+    #[instrument(ret)]
+    fn generic_parse_method<T: std::str::FromStr + std::fmt::Debug>(
+        &self,
+        input: &str,
+    ) -> Result<Vec<T>>
+    where
+        T::Err: std::error::Error + Send + Sync + 'static,
+    {
+        let mut values = Vec::new();
+        for part in input.split(',').take(self.id as usize) {
+            values.push(part.trim().parse::<T>()?);
+        }
+        Ok(values)
+    }
+
+    #[instrument]
+    #[allow(clippy::boxed_local)]
+    fn boxed_consume(self: Box<Self>, suffix: &str) -> Result<String> {
+        Ok(format!("{}{suffix}", self.id))
+    }
+
+    #[instrument]
+    async fn boxed_consume_async(self: Box<Self>) -> Result<u32> {
+        Ok(self.id)
+    }
+
+    #[instrument]
+    fn rc_id(self: std::rc::Rc<Self>) -> Result<u32> {
+        Ok(self.id)
+    }
+
+    #[instrument]
+    async fn arc_id(self: std::sync::Arc<Self>) -> Result<u32> {
+        Ok(self.id)
+    }
+
+    #[instrument]
+    async fn generic_parse_method_async<T: std::str::FromStr>(&self, input: &str) -> Result<T>
+    where
+        T::Err: std::error::Error + Send + Sync + 'static,
+    {
+        Ok(input.parse::<T>()?)
+    }
+}
+
+// Test unsafe functions
+#[instrument(ret)]
+unsafe fn unsafe_read(ptr: *const u32) -> Result<u32> {
+    Ok(unsafe { *ptr })
+}
+
+#[instrument(ret)]
+async unsafe fn async_unsafe_read(ptr: *const u32) -> Result<u32> {
+    Ok(unsafe { *ptr })
+}
+
+// Test async functions whose futures are !Send
+#[instrument(ret)]
+async fn not_send_function(value: u32) -> Result<u32> {
+    let shared = std::rc::Rc::new(value);
+    tokio::task::yield_now().await;
+    Ok(*shared * 2)
+}
+
+// Test trait impl methods returning Self and using associated types
+trait Builder: Sized {
+    type Id;
+    fn with_id(self, id: Self::Id) -> Result<Self>;
+    async fn validated(self) -> Result<Self>;
+}
+
+#[instrument_impl]
+impl Builder for _Test {
+    type Id = u32;
+
+    #[instrument(fields(previous_id = self.id))]
+    fn with_id(mut self, id: Self::Id) -> Result<Self> {
+        self.id = id;
+        Ok(self)
+    }
+
+    #[instrument(skip_all, err = e.as_ref())]
+    async fn validated(self) -> Result<Self> {
+        if self.id == 0 {
+            bail!("Invalid id");
+        }
+        Ok(self)
+    }
+}
+
+// Test several trait impls with methods of the same name on one type, next to an
+// inherent method of that name
+trait Describe {
+    fn describe(&self) -> Result<String>;
+}
+
+trait Summarize {
+    fn describe(&self) -> Result<String>;
+}
+
+#[instrument_impl]
+impl Describe for _Test {
+    fn describe(&self) -> Result<String> {
+        Ok(format!("described {}", self.id))
+    }
+}
+
+#[instrument_impl]
+impl Summarize for _Test {
+    fn describe(&self) -> Result<String> {
+        Ok(format!("summarized {}", self.id))
+    }
+}
+
+#[instrument_impl]
+impl TryFrom<u64> for _Test {
+    type Error = eyre::Report;
+
+    fn try_from(id: u64) -> Result<Self> {
+        Ok(_Test { id: u32::try_from(id)? })
+    }
+}
+
+#[instrument_impl]
+impl TryFrom<&str> for _Test {
+    type Error = eyre::Report;
+
+    fn try_from(id: &str) -> Result<Self> {
+        Ok(_Test { id: id.parse()? })
+    }
+}
+
+impl _Test {
+    #[instrument]
+    fn describe(&self) -> Result<String> {
+        Ok(format!("inherent {}", self.id))
+    }
+}
+
+// Test trait impls on foreign types
+#[instrument_impl]
+impl Describe for u32 {
+    fn describe(&self) -> Result<String> {
+        Ok(format!("number {self}"))
+    }
+}
+
+// Test free functions with nested impls referring to their own `Self`
+#[instrument(ret)]
+fn nested_impl_function(x: u32) -> Result<u32> {
+    struct Doubled(u32);
+    impl Doubled {
+        fn new(x: u32) -> Self {
+            Doubled(x * 2)
+        }
+    }
+    Ok(Doubled::new(x).0)
+}
+
 #[tokio::test]
 async fn test_successful_instrumentation() {
     let tracer_provider = setup_otlp_tracer().unwrap();
@@ -539,3 +731,120 @@ async fn test_tuple_struct_destructuring() {
     assert_eq!(result.unwrap(), "State: app_state");
     tracer_provider.shutdown().unwrap();
 }
+
+#[test]
+fn test_generic_where_clause() {
+    let tracer_provider = setup_otlp_tracer().unwrap();
+    let result: Result<i64> = generic_where_convert(7i32);
+    assert_eq!(result.unwrap(), 7);
+    let result: Result<i64> = generic_where_convert(0i32);
+    assert!(result.is_err());
+    let result = generic_where_default::<u8, 2>();
+    assert_eq!(result.unwrap(), "[0, 0]");
+    tracer_provider.shutdown().unwrap();
+}
+
+#[tokio::test]
+async fn test_generic_where_clause_method() {
+    let tracer_provider = setup_otlp_tracer().unwrap();
+    let test_instance = _Test { id: 5 };
+    let result = test_instance.generic_where_method("value").await;
+    assert_eq!(result.unwrap(), "value for 5");
+    tracer_provider.shutdown().unwrap();
+}
+
+#[tokio::test]
+async fn test_generic_method_turbofish() {
+    let tracer_provider = setup_otlp_tracer().unwrap();
+    let test_instance = _Test { id: 2 };
+    let result = test_instance.generic_parse_method::<u8>("1, 2, 3");
+    assert_eq!(result.unwrap(), vec![1, 2]);
+    let result = test_instance.generic_parse_method::<u8>("1, x");
+    assert!(result.is_err());
+    let result = test_instance.generic_parse_method_async::<f64>("0.5").await;
+    assert_eq!(result.unwrap(), 0.5);
+    tracer_provider.shutdown().unwrap();
+}
+
+#[tokio::test]
+async fn test_smart_pointer_receivers() {
+    let tracer_provider = setup_otlp_tracer().unwrap();
+    let result = Box::new(_Test { id: 3 }).boxed_consume("!");
+    assert_eq!(result.unwrap(), "3!");
+    let result = Box::new(_Test { id: 4 }).boxed_consume_async().await;
+    assert_eq!(result.unwrap(), 4);
+    let result = std::rc::Rc::new(_Test { id: 5 }).rc_id();
+    assert_eq!(result.unwrap(), 5);
+    let result = std::sync::Arc::new(_Test { id: 6 }).arc_id().await;
+    assert_eq!(result.unwrap(), 6);
+    tracer_provider.shutdown().unwrap();
+}
+
+#[test]
+fn test_unsafe_function() {
+    let tracer_provider = setup_otlp_tracer().unwrap();
+    let value = 42u32;
+    let result = unsafe { unsafe_read(&value) };
+    assert_eq!(result.unwrap(), 42);
+    tracer_provider.shutdown().unwrap();
+}
+
+#[tokio::test]
+async fn test_async_unsafe_function() {
+    let tracer_provider = setup_otlp_tracer().unwrap();
+    let value = 7u32;
+    let result = unsafe { async_unsafe_read(&value) }.await;
+    assert_eq!(result.unwrap(), 7);
+    tracer_provider.shutdown().unwrap();
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn test_not_send_async_function() {
+    let tracer_provider = setup_otlp_tracer().unwrap();
+    let result = not_send_function(21).await;
+    assert_eq!(result.unwrap(), 42);
+
+    // Also run it on a LocalSet, which is how !Send futures are usually spawned
+    let local = tokio::task::LocalSet::new();
+    let result = local
+        .run_until(async { tokio::task::spawn_local(not_send_function(1)).await })
+        .await;
+    assert_eq!(result.unwrap().unwrap(), 2);
+    tracer_provider.shutdown().unwrap();
+}
+
+#[tokio::test]
+async fn test_trait_impl_returning_self() {
+    let tracer_provider = setup_otlp_tracer().unwrap();
+    let built = _Test { id: 0 }.with_id(9).unwrap();
+    assert_eq!(built.id, 9);
+    let validated = built.validated().await.unwrap();
+    assert_eq!(validated.id, 9);
+    assert!(_Test { id: 0 }.validated().await.is_err());
+    tracer_provider.shutdown().unwrap();
+}
+
+#[tokio::test]
+async fn test_trait_impls_with_same_method_names() {
+    let tracer_provider = setup_otlp_tracer().unwrap();
+    let test = _Test { id: 4 };
+    assert_eq!(Describe::describe(&test).unwrap(), "described 4");
+    assert_eq!(Summarize::describe(&test).unwrap(), "summarized 4");
+    assert_eq!(test.describe().unwrap(), "inherent 4");
+    assert_eq!(_Test::try_from(5u64).unwrap().id, 5);
+    assert_eq!(_Test::try_from("6").unwrap().id, 6);
+    assert!(_Test::try_from(u64::MAX).is_err());
+    assert_eq!(7u32.describe().unwrap(), "number 7");
+
+    // Futures of async trait methods stay `Send`
+    let validated = tokio::spawn(_Test { id: 8 }.validated()).await.unwrap();
+    assert_eq!(validated.unwrap().id, 8);
+    tracer_provider.shutdown().unwrap();
+}
+
+#[tokio::test]
+async fn test_free_function_with_nested_impl() {
+    let tracer_provider = setup_otlp_tracer().unwrap();
+    assert_eq!(nested_impl_function(21).unwrap(), 42);
+    tracer_provider.shutdown().unwrap();
+}
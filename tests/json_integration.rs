@@ -0,0 +1,41 @@
+#![cfg(feature = "json")]
+
+use opentelemetry::{Value, global};
+use opentelemetry_sdk::trace::{InMemorySpanExporter, SdkTracerProvider};
+use otel_instrument::{instrument, tracer_name};
+
+tracer_name!("otel-instrument-json-tests");
+
+#[derive(serde::Serialize)]
+struct CreateUser {
+    name: String,
+    admin: bool,
+}
+
+#[instrument(skip(body), fields(body = json(body)))]
+fn create_user(body: &CreateUser) -> Result<(), String> {
+    Ok(())
+}
+
+#[test]
+fn test_json_field() {
+    let exporter = InMemorySpanExporter::default();
+    let tracer_provider = SdkTracerProvider::builder()
+        .with_simple_exporter(exporter.clone())
+        .build();
+    global::set_tracer_provider(tracer_provider);
+
+    create_user(&CreateUser {
+        name: "jane".into(),
+        admin: false,
+    })
+    .unwrap();
+
+    let spans = exporter.get_finished_spans().unwrap();
+    let body = spans[0]
+        .attributes
+        .iter()
+        .find(|kv| kv.key.as_str() == "body")
+        .map(|kv| kv.value.clone());
+    assert_eq!(body, Some(Value::from(r#"{"name":"jane","admin":false}"#)));
+}
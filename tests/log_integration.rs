@@ -0,0 +1,60 @@
+#![cfg(feature = "log")]
+
+use otel_instrument::{instrument, tracer_name};
+use std::sync::Mutex;
+
+tracer_name!("otel-instrument-log-tests");
+
+// Minimal logger capturing every record, so the emitted lines can be asserted
+struct CaptureLogger(Mutex<Vec<(log::Level, String)>>);
+
+impl log::Log for CaptureLogger {
+    fn enabled(&self, _metadata: &log::Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &log::Record) {
+        self.0
+            .lock()
+            .unwrap()
+            .push((record.level(), record.args().to_string()));
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: CaptureLogger = CaptureLogger(Mutex::new(Vec::new()));
+
+#[derive(thiserror::Error, Debug)]
+#[error("disk full")]
+struct DiskFull;
+
+#[instrument(err(log))]
+fn log_on_error(fail: bool) -> Result<(), DiskFull> {
+    if fail { Err(DiskFull) } else { Ok(()) }
+}
+
+#[instrument(err(log))]
+async fn async_log_on_error() -> Result<(), DiskFull> {
+    Err(DiskFull)
+}
+
+#[tokio::test]
+async fn test_err_log() {
+    log::set_logger(&LOGGER).unwrap();
+    log::set_max_level(log::LevelFilter::Trace);
+
+    log_on_error(false).unwrap();
+    assert!(LOGGER.0.lock().unwrap().is_empty());
+
+    log_on_error(true).unwrap_err();
+    async_log_on_error().await.unwrap_err();
+    let records = LOGGER.0.lock().unwrap();
+    assert_eq!(
+        *records,
+        [
+            (log::Level::Error, "DiskFull".to_string()),
+            (log::Level::Error, "DiskFull".to_string())
+        ]
+    );
+}
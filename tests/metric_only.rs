@@ -0,0 +1,92 @@
+use opentelemetry::global;
+use opentelemetry::{KeyValue, Value};
+use opentelemetry_sdk::metrics::data::{AggregatedMetrics, MetricData};
+use opentelemetry_sdk::metrics::{InMemoryMetricExporter, SdkMeterProvider};
+use opentelemetry_sdk::trace::{InMemorySpanExporter, SdkTracerProvider};
+use otel_instrument::{instrument, tracer_name};
+
+tracer_name!("otel-instrument-metric-tests");
+
+#[instrument(metric_only)]
+fn hot_path(fail: bool) -> Result<u32, String> {
+    if fail { Err("failed".into()) } else { Ok(1) }
+}
+
+#[instrument(metric_only, name = "hot_path_async")]
+async fn hot_path_async() -> Result<(), String> {
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_metric_only() {
+    let span_exporter = InMemorySpanExporter::default();
+    global::set_tracer_provider(
+        SdkTracerProvider::builder()
+            .with_simple_exporter(span_exporter.clone())
+            .build(),
+    );
+    let metric_exporter = InMemoryMetricExporter::default();
+    let meter_provider = SdkMeterProvider::builder()
+        .with_periodic_exporter(metric_exporter.clone())
+        .build();
+    global::set_meter_provider(meter_provider.clone());
+
+    hot_path(false).unwrap();
+    hot_path(false).unwrap();
+    hot_path(true).unwrap_err();
+    hot_path_async().await.unwrap();
+    meter_provider.force_flush().unwrap();
+
+    assert!(span_exporter.get_finished_spans().unwrap().is_empty());
+
+    let metrics = metric_exporter.get_finished_metrics().unwrap();
+    let metrics: Vec<_> = metrics
+        .iter()
+        .flat_map(|resource| resource.scope_metrics())
+        .flat_map(|scope| scope.metrics())
+        .collect();
+
+    let calls = metrics
+        .iter()
+        .find(|metric| metric.name() == "function.calls")
+        .expect("call counter was not exported");
+    let AggregatedMetrics::U64(MetricData::Sum(calls)) = calls.data() else {
+        panic!("unexpected call counter data");
+    };
+    let mut counts: Vec<_> = calls
+        .data_points()
+        .map(|point| {
+            let mut attributes: Vec<_> = point.attributes().cloned().collect();
+            attributes.sort_by(|a, b| a.key.cmp(&b.key));
+            (attributes, point.value())
+        })
+        .collect();
+    counts.sort_by_key(|(attributes, _)| format!("{attributes:?}"));
+    let function = |name: &'static str| KeyValue::new("code.function", Value::from(name));
+    assert_eq!(
+        counts,
+        [
+            (vec![function("hot_path"), KeyValue::new("error", false)], 2),
+            (vec![function("hot_path"), KeyValue::new("error", true)], 1),
+            (
+                vec![function("hot_path_async"), KeyValue::new("error", false)],
+                1
+            ),
+        ]
+    );
+
+    let duration = metrics
+        .iter()
+        .find(|metric| metric.name() == "function.duration")
+        .expect("duration histogram was not exported");
+    let AggregatedMetrics::F64(MetricData::Histogram(duration)) = duration.data() else {
+        panic!("unexpected duration histogram data");
+    };
+    assert_eq!(
+        duration
+            .data_points()
+            .map(|point| point.count())
+            .sum::<u64>(),
+        4
+    );
+}
@@ -0,0 +1,63 @@
+use opentelemetry::{Value, global};
+use opentelemetry_sdk::trace::{InMemorySpanExporter, SdkTracerProvider, SpanData};
+use otel_instrument::{instrument, seeded_id_generator, tracer_name};
+
+tracer_name!("otel-instrument-seeded-tests");
+seeded_id_generator!();
+
+#[instrument(test_ids)]
+fn seeded_child() -> Result<(), String> {
+    Ok(())
+}
+
+#[instrument(test_ids)]
+fn seeded_parent() -> Result<(), String> {
+    seeded_child()
+}
+
+fn attribute(span: &SpanData, key: &str) -> Option<Value> {
+    span.attributes
+        .iter()
+        .find(|kv| kv.key.as_str() == key)
+        .map(|kv| kv.value.clone())
+}
+
+#[test]
+fn test_seeded_ids() {
+    let exporter = InMemorySpanExporter::default();
+    global::set_tracer_provider(
+        SdkTracerProvider::builder()
+            .with_simple_exporter(exporter.clone())
+            .with_id_generator(SeededIdGenerator::new(42))
+            .build(),
+    );
+
+    seeded_parent().unwrap();
+
+    let spans = exporter.get_finished_spans().unwrap();
+    let parent = spans
+        .iter()
+        .find(|span| span.name == "seeded_parent")
+        .unwrap();
+    let child = spans
+        .iter()
+        .find(|span| span.name == "seeded_child")
+        .unwrap();
+    // The parent starts the first trace with the first span id, and the child
+    // continues the trace with the second one
+    let trace_id = Value::from("000000000000002a0000000000000001");
+    assert_eq!(attribute(parent, "trace_id"), Some(trace_id.clone()));
+    assert_eq!(
+        attribute(parent, "span_id"),
+        Some(Value::from("000000000000002b"))
+    );
+    assert_eq!(attribute(child, "trace_id"), Some(trace_id));
+    assert_eq!(
+        attribute(child, "span_id"),
+        Some(Value::from("000000000000002c"))
+    );
+    assert_eq!(
+        parent.span_context.span_id().to_string(),
+        "000000000000002b"
+    );
+}
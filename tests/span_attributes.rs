@@ -0,0 +1,2867 @@
+use opentelemetry::trace::{Span, SpanId, SpanKind, Status, TraceContextExt, Tracer};
+use opentelemetry::{Context, KeyValue, Value, global};
+use opentelemetry_sdk::trace::{InMemorySpanExporter, SdkTracerProvider, SpanData};
+use otel_instrument::{
+    checkpoint, instrument, instrument_closure, instrument_impl, runtime_instrument,
+    set_span_status, task_local_context, tracer_name,
+};
+use std::sync::OnceLock;
+
+tracer_name!("otel-instrument-attribute-tests");
+task_local_context!();
+runtime_instrument!();
+
+// Install a single in-memory exporter for the whole test binary, so spans can
+// be inspected after the instrumented function returns.
+fn exporter() -> &'static InMemorySpanExporter {
+    static EXPORTER: OnceLock<InMemorySpanExporter> = OnceLock::new();
+    EXPORTER.get_or_init(|| {
+        let exporter = InMemorySpanExporter::default();
+        let tracer_provider = SdkTracerProvider::builder()
+            .with_simple_exporter(exporter.clone())
+            .build();
+        global::set_tracer_provider(tracer_provider);
+        exporter
+    })
+}
+
+// Tests run in parallel, so every test uses a unique span name to find its span
+fn finished_span(name: &str) -> SpanData {
+    exporter()
+        .get_finished_spans()
+        .unwrap()
+        .into_iter()
+        .find(|span| span.name == name)
+        .unwrap_or_else(|| panic!("span `{name}` was not exported"))
+}
+
+fn attribute(span: &SpanData, key: &str) -> Option<Value> {
+    span.attributes
+        .iter()
+        .find(|kv| kv.key.as_str() == key)
+        .map(|kv| kv.value.clone())
+}
+
+#[instrument(record_parent_id)]
+fn record_parent_id_child() -> Result<(), String> {
+    Ok(())
+}
+
+#[instrument(record_parent_id)]
+fn record_parent_id_orphan() -> Result<(), String> {
+    Ok(())
+}
+
+#[test]
+fn test_record_parent_id() {
+    exporter();
+    let tracer = global::tracer("test-tracer");
+    let parent_span = tracer.start("record_parent_id_parent");
+    let parent_span_id = parent_span.span_context().span_id();
+    let parent_ctx = Context::current_with_span(parent_span);
+    {
+        let _guard = parent_ctx.clone().attach();
+        record_parent_id_child().unwrap();
+    }
+    parent_ctx.span().end();
+
+    let span = finished_span("record_parent_id_child");
+    assert_eq!(
+        attribute(&span, "parent.span_id"),
+        Some(Value::from(parent_span_id.to_string()))
+    );
+    assert_eq!(span.parent_span_id, parent_span_id);
+}
+
+#[test]
+fn test_record_parent_id_without_parent() {
+    exporter();
+    record_parent_id_orphan().unwrap();
+    let span = finished_span("record_parent_id_orphan");
+    assert_eq!(attribute(&span, "parent.span_id"), None);
+}
+
+#[instrument(ret)]
+fn ret_unit_result() -> Result<(), String> {
+    Ok(())
+}
+
+#[instrument(ret)]
+fn ret_value_result() -> Result<u32, String> {
+    Ok(7)
+}
+
+#[test]
+fn test_ret_skips_unit_result() {
+    exporter();
+    ret_unit_result().unwrap();
+    let span = finished_span("ret_unit_result");
+    assert_eq!(attribute(&span, "return"), None);
+    assert_eq!(span.status, Status::Ok);
+}
+
+#[test]
+fn test_ret_records_value_result() {
+    exporter();
+    ret_value_result().unwrap();
+    let span = finished_span("ret_value_result");
+    assert_eq!(attribute(&span, "return"), Some(Value::from("7")));
+    assert_eq!(span.status, Status::Ok);
+}
+
+#[instrument(skip_all, fields(size = bytes(data.len()), elapsed = millis(elapsed)))]
+fn unit_fields(data: &[u8], elapsed: std::time::Duration) -> Result<(), String> {
+    let _ = (data, elapsed);
+    Ok(())
+}
+
+#[test]
+fn test_unit_fields() {
+    exporter();
+    unit_fields(&[0; 16], std::time::Duration::from_millis(250)).unwrap();
+    let span = finished_span("unit_fields");
+    assert_eq!(attribute(&span, "size"), Some(Value::I64(16)));
+    assert_eq!(attribute(&span, "size.unit"), Some(Value::from("By")));
+    assert_eq!(attribute(&span, "elapsed"), Some(Value::I64(250)));
+    assert_eq!(attribute(&span, "elapsed.unit"), Some(Value::from("ms")));
+}
+
+#[instrument(task_local)]
+async fn task_local_parent() -> Result<(), String> {
+    spawn_in_context(task_local_child()).await.unwrap()
+}
+
+#[instrument(task_local)]
+async fn task_local_child() -> Result<(), String> {
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_task_local_spawned_child() {
+    exporter();
+    task_local_parent().await.unwrap();
+    let parent = finished_span("task_local_parent");
+    let child = finished_span("task_local_child");
+    assert_eq!(child.parent_span_id, parent.span_context.span_id());
+    assert_eq!(
+        child.span_context.trace_id(),
+        parent.span_context.trace_id()
+    );
+}
+
+#[derive(thiserror::Error, Debug)]
+enum FetchError {
+    #[error("request timed out")]
+    Timeout,
+    #[error("not found")]
+    NotFound,
+}
+
+fn is_retryable(e: &FetchError) -> bool {
+    matches!(e, FetchError::Timeout)
+}
+
+#[instrument(err(retryable = is_retryable(e)))]
+fn retryable_error() -> Result<(), FetchError> {
+    Err(FetchError::Timeout)
+}
+
+#[instrument(err(retryable = is_retryable(e)))]
+fn fatal_error() -> Result<(), FetchError> {
+    Err(FetchError::NotFound)
+}
+
+#[test]
+fn test_err_retryable() {
+    exporter();
+    assert!(retryable_error().is_err());
+    let span = finished_span("retryable_error");
+    assert_eq!(attribute(&span, "error.retryable"), Some(Value::Bool(true)));
+    assert_eq!(attribute(&span, "error"), Some(Value::from("Timeout")));
+    assert_eq!(span.status, Status::Unset);
+}
+
+#[test]
+fn test_err_fatal() {
+    exporter();
+    assert!(fatal_error().is_err());
+    let span = finished_span("fatal_error");
+    assert_eq!(attribute(&span, "error.retryable"), None);
+    assert_eq!(span.status, Status::error("NotFound"));
+}
+
+#[instrument(err(no_attr))]
+fn error_without_attr() -> Result<(), FetchError> {
+    Err(FetchError::NotFound)
+}
+
+#[test]
+fn test_err_no_attr() {
+    exporter();
+    assert!(error_without_attr().is_err());
+    let span = finished_span("error_without_attr");
+    assert_eq!(attribute(&span, "error"), None);
+    assert_eq!(span.status, Status::error("NotFound"));
+    assert_eq!(span.events.len(), 1);
+}
+
+fn is_unexpected(e: &FetchError) -> bool {
+    !matches!(e, FetchError::NotFound)
+}
+
+#[instrument(err(record_if = is_unexpected(e)))]
+async fn lookup_unexpected() -> Result<(), FetchError> {
+    Err(FetchError::Timeout)
+}
+
+#[instrument(err(record_if = is_unexpected(e)))]
+async fn lookup_expected() -> Result<(), FetchError> {
+    Err(FetchError::NotFound)
+}
+
+#[tokio::test]
+async fn test_err_record_if_recorded() {
+    exporter();
+    assert!(lookup_unexpected().await.is_err());
+    let span = finished_span("lookup_unexpected");
+    assert_eq!(span.status, Status::error("Timeout"));
+    assert_eq!(attribute(&span, "error.expected"), None);
+    assert_eq!(span.events.len(), 1);
+}
+
+#[tokio::test]
+async fn test_err_record_if_expected() {
+    exporter();
+    assert!(lookup_expected().await.is_err());
+    let span = finished_span("lookup_expected");
+    assert_eq!(span.status, Status::Unset);
+    assert_eq!(attribute(&span, "error"), Some(Value::from("NotFound")));
+    assert_eq!(attribute(&span, "error.expected"), Some(Value::Bool(true)));
+    assert!(span.events.is_empty());
+}
+
+#[test]
+fn test_runtime_instrument_fn() {
+    exporter();
+    let result = instrument_fn(
+        "runtime_sync_ok",
+        vec![KeyValue::new("handler", "sync")],
+        SpanKind::Server,
+        || Ok::<_, FetchError>(3),
+    );
+    assert_eq!(result.unwrap(), 3);
+    let span = finished_span("runtime_sync_ok");
+    assert_eq!(span.span_kind, SpanKind::Server);
+    assert_eq!(attribute(&span, "handler"), Some(Value::from("sync")));
+    assert_eq!(span.status, Status::Ok);
+
+    let result = instrument_fn("runtime_sync_err", Vec::new(), SpanKind::Internal, || {
+        Err::<(), _>(FetchError::NotFound)
+    });
+    assert!(result.is_err());
+    let span = finished_span("runtime_sync_err");
+    assert_eq!(attribute(&span, "error"), Some(Value::from("NotFound")));
+    assert_eq!(span.status, Status::error("NotFound"));
+}
+
+#[tokio::test]
+async fn test_runtime_instrument_fn_async() {
+    exporter();
+    let result = instrument_fn_async(
+        String::from("runtime_async_ok"),
+        vec![KeyValue::new("handler", "async")],
+        SpanKind::Client,
+        || async { Ok::<_, FetchError>("done") },
+    )
+    .await;
+    assert_eq!(result.unwrap(), "done");
+    let span = finished_span("runtime_async_ok");
+    assert_eq!(span.span_kind, SpanKind::Client);
+    assert_eq!(attribute(&span, "handler"), Some(Value::from("async")));
+    assert_eq!(span.status, Status::Ok);
+
+    let result = instrument_fn_async(
+        "runtime_async_err",
+        Vec::new(),
+        SpanKind::Internal,
+        || async { Err::<(), _>(FetchError::Timeout) },
+    )
+    .await;
+    assert!(result.is_err());
+    let span = finished_span("runtime_async_err");
+    assert_eq!(span.status, Status::error("Timeout"));
+}
+
+// `e` is also a parameter here, which must not be confused with the matched error
+#[instrument(err, fields(input = e))]
+fn outer_e_binding(e: &str) -> Result<(), FetchError> {
+    let _ = e;
+    Err(FetchError::NotFound)
+}
+
+#[instrument(err(retryable = is_retryable(e)), fields(e))]
+fn outer_e_retryable(e: u32) -> Result<u32, FetchError> {
+    if e > 0 {
+        Err(FetchError::Timeout)
+    } else {
+        Ok(e)
+    }
+}
+
+#[test]
+fn test_err_binding_with_outer_e() {
+    exporter();
+    assert!(outer_e_binding("param").is_err());
+    let span = finished_span("outer_e_binding");
+    assert_eq!(attribute(&span, "input"), Some(Value::from("param")));
+    assert_eq!(attribute(&span, "error"), Some(Value::from("NotFound")));
+    assert_eq!(span.status, Status::error("NotFound"));
+
+    assert!(outer_e_retryable(3).is_err());
+    let span = finished_span("outer_e_retryable");
+    assert_eq!(attribute(&span, "e"), Some(Value::I64(3)));
+    assert_eq!(attribute(&span, "error.retryable"), Some(Value::Bool(true)));
+}
+
+#[instrument(skip_all, fields(user = hash(user_id), name = hash(name)))]
+fn hashed_fields(user_id: u64, name: &str) -> Result<(), String> {
+    let _ = (user_id, name);
+    Ok(())
+}
+
+#[test]
+fn test_hashed_fields() {
+    exporter();
+    hashed_fields(42, "alice").unwrap();
+    let span = finished_span("hashed_fields");
+    // FNV-1a of the Debug representations `42` and `"alice"`
+    assert_eq!(
+        attribute(&span, "user"),
+        Some(Value::from("07ee7e07b4b19223"))
+    );
+    assert_eq!(
+        attribute(&span, "name"),
+        Some(Value::from("0644316f7470dd1f"))
+    );
+}
+
+#[instrument(err)]
+fn boxed_error() -> Result<u32, Box<dyn std::error::Error>> {
+    Err(Box::new(FetchError::NotFound))
+}
+
+#[instrument(err)]
+async fn boxed_send_sync_error() -> Result<u32, Box<dyn std::error::Error + Send + Sync>> {
+    Err("plain message".into())
+}
+
+#[test]
+fn test_boxed_error() {
+    exporter();
+    assert!(boxed_error().is_err());
+    let span = finished_span("boxed_error");
+    assert_eq!(span.status, Status::error("NotFound"));
+    assert_eq!(span.events.len(), 1);
+}
+
+#[tokio::test]
+async fn test_boxed_send_sync_error() {
+    exporter();
+    assert!(boxed_send_sync_error().await.is_err());
+    let span = finished_span("boxed_send_sync_error");
+    assert_eq!(
+        attribute(&span, "error"),
+        Some(Value::from("\"plain message\""))
+    );
+    assert_eq!(span.events.len(), 1);
+}
+
+#[instrument(status = manual, fields(source = "manual"))]
+fn manual_status(value: u32) -> Result<u32, String> {
+    Ok(value)
+}
+
+#[instrument(status = manual)]
+fn manual_status_error(value: u32) -> Result<u32, String> {
+    Err(format!("failed with {value}"))
+}
+
+#[test]
+fn test_manual_status() {
+    exporter();
+    manual_status(1).unwrap();
+    let span = finished_span("manual_status");
+    assert_eq!(attribute(&span, "value"), Some(Value::I64(1)));
+    assert_eq!(attribute(&span, "source"), Some(Value::from("manual")));
+    assert_eq!(span.status, Status::Unset);
+
+    manual_status_error(2).unwrap_err();
+    let span = finished_span("manual_status_error");
+    assert_eq!(attribute(&span, "value"), Some(Value::I64(2)));
+    assert_eq!(attribute(&span, "error"), None);
+    assert_eq!(span.status, Status::Unset);
+}
+
+#[instrument(status = manual, ret, err)]
+fn manual_status_captures(value: u32) -> Result<u32, FetchError> {
+    if value > 0 {
+        Ok(value * 2)
+    } else {
+        Err(FetchError::NotFound)
+    }
+}
+
+#[test]
+fn test_manual_status_captures() {
+    exporter();
+    manual_status_captures(2).unwrap();
+    manual_status_captures(0).unwrap_err();
+    let spans = exporter().get_finished_spans().unwrap();
+    let spans: Vec<_> = spans
+        .iter()
+        .filter(|span| span.name == "manual_status_captures")
+        .collect();
+    // The attributes of `ret` and `err` are recorded, while the status is left alone
+    assert_eq!(attribute(spans[0], "return"), Some(Value::from("4")));
+    assert_eq!(spans[0].status, Status::Unset);
+    assert_eq!(attribute(spans[1], "error"), Some(Value::from("NotFound")));
+    assert_eq!(spans[1].status, Status::Unset);
+    assert!(
+        spans[1]
+            .events
+            .iter()
+            .any(|event| event.name == "exception")
+    );
+}
+
+#[instrument(ret(rows = .0.len(), first = .0[0], empty = .0.is_empty(), elapsed = .1))]
+fn ret_projections() -> Result<(Vec<&'static str>, u64), String> {
+    Ok((vec!["a", "b", "c"], 12))
+}
+
+#[test]
+fn test_ret_projections() {
+    exporter();
+    ret_projections().unwrap();
+    let span = finished_span("ret_projections");
+    // Recorded like parameters, as typed values where supported
+    assert_eq!(attribute(&span, "rows"), Some(Value::from("3")));
+    assert_eq!(attribute(&span, "first"), Some(Value::from("a")));
+    assert_eq!(attribute(&span, "empty"), Some(Value::Bool(false)));
+    assert_eq!(attribute(&span, "elapsed"), Some(Value::from("12")));
+    assert_eq!(attribute(&span, "return"), None);
+}
+
+// Parameters sharing names with bindings the macro generates
+#[instrument(ret, err, fields(tracer_len = tracer.len()))]
+fn internal_binding_names(span: u32, tracer: &str, result: bool) -> Result<u32, FetchError> {
+    if result {
+        Ok(span * tracer.len() as u32)
+    } else {
+        Err(FetchError::NotFound)
+    }
+}
+
+#[instrument(fields(span, _guard))]
+async fn async_internal_binding_names(span: &str, _guard: u8) -> Result<String, String> {
+    Ok(span.to_uppercase())
+}
+
+#[tokio::test]
+async fn test_internal_binding_names() {
+    exporter();
+    assert_eq!(internal_binding_names(14, "abc", true).unwrap(), 42);
+    let span = finished_span("internal_binding_names");
+    assert_eq!(attribute(&span, "span"), Some(Value::I64(14)));
+    assert_eq!(attribute(&span, "tracer"), Some(Value::from("abc")));
+    assert_eq!(attribute(&span, "result"), Some(Value::Bool(true)));
+    assert_eq!(attribute(&span, "tracer_len"), Some(Value::from("3")));
+    assert_eq!(attribute(&span, "return"), Some(Value::from("42")));
+
+    let result = async_internal_binding_names("value", 1).await;
+    assert_eq!(result.unwrap(), "VALUE");
+    let span = finished_span("async_internal_binding_names");
+    assert_eq!(attribute(&span, "span"), Some(Value::from("value")));
+    assert_eq!(attribute(&span, "_guard"), Some(Value::I64(1)));
+    assert_eq!(span.status, Status::Ok);
+}
+
+#[instrument(skip_all, start_time = _enqueued_at)]
+fn backdated_start(_enqueued_at: std::time::SystemTime) -> Result<(), String> {
+    Ok(())
+}
+
+#[test]
+fn test_start_time() {
+    exporter();
+    let enqueued_at = std::time::SystemTime::now() - std::time::Duration::from_secs(60);
+    backdated_start(enqueued_at).unwrap();
+    let span = finished_span("backdated_start");
+    assert_eq!(span.start_time, enqueued_at);
+    assert!(
+        span.end_time
+            .duration_since(span.start_time)
+            .unwrap()
+            .as_secs()
+            >= 60
+    );
+}
+
+#[instrument(package_info)]
+fn with_package_info() -> Result<(), String> {
+    Ok(())
+}
+
+#[test]
+fn test_package_info() {
+    exporter();
+    with_package_info().unwrap();
+    let span = finished_span("with_package_info");
+    assert_eq!(
+        attribute(&span, "code.package"),
+        Some(Value::from(env!("CARGO_PKG_NAME")))
+    );
+    assert_eq!(
+        attribute(&span, "code.package.version"),
+        Some(Value::from(env!("CARGO_PKG_VERSION")))
+    );
+}
+
+#[instrument(skip_all, fields(parsed = try(input.parse::<u32>())))]
+fn fallible_field(input: &str) -> Result<usize, String> {
+    Ok(input.len())
+}
+
+#[test]
+fn test_fallible_field() {
+    exporter();
+    assert_eq!(fallible_field("not a number").unwrap(), 12);
+    let span = finished_span("fallible_field");
+    assert_eq!(attribute(&span, "parsed"), Some(Value::from("<err>")));
+    assert_eq!(span.status, Status::Ok);
+}
+
+#[instrument(skip_all, fields(parsed = try(input.parse::<u32>())))]
+fn fallible_field_ok(input: &str) -> Result<usize, String> {
+    Ok(input.len())
+}
+
+#[test]
+fn test_fallible_field_ok() {
+    exporter();
+    fallible_field_ok("17").unwrap();
+    let span = finished_span("fallible_field_ok");
+    assert_eq!(attribute(&span, "parsed"), Some(Value::I64(17)));
+}
+
+#[derive(Debug)]
+struct Email(String);
+
+// Redact everything but the domain
+impl _otel::SpanAttributeValue for Email {
+    fn to_span_value(&self) -> Value {
+        let domain = self.0.split_once('@').map_or("", |(_, domain)| domain);
+        Value::from(format!("***@{domain}"))
+    }
+}
+
+#[derive(Debug)]
+struct Plain(u8);
+
+#[instrument(fields(contact = &email, count = 3u64, ratio = 0.5))]
+fn custom_attribute_value(email: Email, plain: Plain, enabled: bool) -> Result<(), String> {
+    let _ = (email, plain.0, enabled);
+    Ok(())
+}
+
+#[test]
+fn test_custom_attribute_value() {
+    exporter();
+    custom_attribute_value(Email("jane@example.com".into()), Plain(4), true).unwrap();
+    let span = finished_span("custom_attribute_value");
+    assert_eq!(
+        attribute(&span, "email"),
+        Some(Value::from("***@example.com"))
+    );
+    assert_eq!(
+        attribute(&span, "contact"),
+        Some(Value::from("***@example.com"))
+    );
+    assert_eq!(attribute(&span, "plain"), Some(Value::from("Plain(4)")));
+    assert_eq!(attribute(&span, "enabled"), Some(Value::Bool(true)));
+    // u64 can't be converted losslessly, so it falls back to Debug
+    assert_eq!(attribute(&span, "count"), Some(Value::from("3")));
+    assert_eq!(attribute(&span, "ratio"), Some(Value::F64(0.5)));
+}
+
+#[instrument(skip_all, fields(len = items.len()), cfg(debug_assertions)(fields(first = items[0])), cfg(any())(fields(never = true)))]
+fn conditional_fields(items: &[u32]) -> Result<usize, String> {
+    Ok(items.len())
+}
+
+#[test]
+fn test_conditional_fields() {
+    exporter();
+    conditional_fields(&[7, 8]).unwrap();
+    let span = finished_span("conditional_fields");
+    assert_eq!(attribute(&span, "len"), Some(Value::from("2")));
+    assert_eq!(
+        attribute(&span, "first"),
+        cfg!(debug_assertions).then_some(Value::I64(7))
+    );
+    assert_eq!(attribute(&span, "never"), None);
+}
+
+#[cfg_attr(debug_assertions, instrument(ret))]
+#[cfg_attr(not(debug_assertions), instrument)]
+fn conditional_ret(value: u32) -> Result<u32, String> {
+    Ok(value * 2)
+}
+
+#[test]
+fn test_conditional_ret() {
+    exporter();
+    assert_eq!(conditional_ret(21).unwrap(), 42);
+    let span = finished_span("conditional_ret");
+    assert_eq!(attribute(&span, "value"), Some(Value::I64(21)));
+    assert_eq!(
+        attribute(&span, "return"),
+        cfg!(debug_assertions).then(|| Value::from("42"))
+    );
+}
+
+#[instrument(parent = remote)]
+fn span_context_parent(remote: opentelemetry::trace::SpanContext) -> Result<(), String> {
+    let _ = remote;
+    Ok(())
+}
+
+#[test]
+fn test_span_context_parent() {
+    exporter();
+    let tracer = global::tracer("test-tracer");
+    let parent_span = tracer.start("span_context_parent_parent");
+    let remote = parent_span.span_context().clone();
+    span_context_parent(remote.clone()).unwrap();
+    let span = finished_span("span_context_parent");
+    assert_eq!(span.parent_span_id, remote.span_id());
+    assert_eq!(span.span_context.trace_id(), remote.trace_id());
+}
+
+#[test]
+fn test_into_parent_context() {
+    let tracer = global::tracer("test-tracer");
+    let ctx = Context::current_with_span(tracer.start("into_parent_context"));
+    let span_context = ctx.span().span_context().clone();
+    assert_eq!(
+        _otel::into_parent_context(&ctx).span().span_context(),
+        &span_context
+    );
+    assert_eq!(
+        _otel::into_parent_context(&&span_context)
+            .span()
+            .span_context(),
+        &span_context
+    );
+}
+
+struct Store {
+    prefix: String,
+}
+
+#[instrument_impl]
+impl Store {
+    const SEPARATOR: &str = "/";
+
+    fn impl_block_get(&self, key: &str) -> Result<String, String> {
+        Ok(format!("{}{}{key}", self.prefix, Self::SEPARATOR))
+    }
+
+    #[skip_instrument]
+    fn impl_block_skipped(&self) -> Result<usize, String> {
+        Ok(self.prefix.len())
+    }
+
+    #[instrument(skip(value))]
+    async fn impl_block_set(&self, key: &str, value: &str) -> Result<(), String> {
+        let _ = (key, value);
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn test_instrument_impl() {
+    exporter();
+    let store = Store {
+        prefix: "users".into(),
+    };
+    assert_eq!(store.impl_block_get("1").unwrap(), "users/1");
+    store.impl_block_set("1", "secret").await.unwrap();
+
+    let get = finished_span("impl_block_get");
+    assert_eq!(attribute(&get, "key"), Some(Value::from("1")));
+    assert_eq!(get.status, Status::Ok);
+
+    assert_eq!(store.impl_block_skipped().unwrap(), 5);
+    assert!(
+        !exporter()
+            .get_finished_spans()
+            .unwrap()
+            .iter()
+            .any(|span| span.name == "impl_block_skipped")
+    );
+
+    let set = finished_span("impl_block_set");
+    assert_eq!(attribute(&set, "key"), Some(Value::from("1")));
+    assert_eq!(attribute(&set, "value"), None);
+}
+
+struct Component {
+    name: &'static str,
+}
+
+#[instrument_impl(common_fields(component = self.name(), layer = "storage"))]
+impl Component {
+    fn common_fields_new(name: &'static str) -> Result<Self, String> {
+        Ok(Self { name })
+    }
+
+    #[skip_instrument]
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    #[instrument]
+    fn common_fields_load(&self) -> Result<(), String> {
+        Ok(())
+    }
+
+    #[instrument(skip(key), fields(kind = "write"))]
+    async fn common_fields_store(&self, key: &str) -> Result<(), String> {
+        let _ = key;
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn test_instrument_impl_common_fields() {
+    exporter();
+    let component = Component::common_fields_new("cache").unwrap();
+    component.common_fields_load().unwrap();
+    component.common_fields_store("1").await.unwrap();
+
+    // Without a receiver, only the fields not derived from `self` are recorded
+    let new = finished_span("common_fields_new");
+    assert_eq!(attribute(&new, "component"), None);
+    assert_eq!(attribute(&new, "layer"), Some(Value::from("storage")));
+
+    for name in ["common_fields_load", "common_fields_store"] {
+        let span = finished_span(name);
+        assert_eq!(attribute(&span, "component"), Some(Value::from("cache")));
+        assert_eq!(attribute(&span, "layer"), Some(Value::from("storage")));
+    }
+    let store = finished_span("common_fields_store");
+    assert_eq!(attribute(&store, "kind"), Some(Value::from("write")));
+    assert_eq!(attribute(&store, "key"), None);
+}
+
+fn baggage_request_id() -> Option<String> {
+    use opentelemetry::baggage::BaggageExt;
+    Context::current()
+        .baggage()
+        .get("request.id")
+        .map(|id| id.to_string())
+}
+
+#[instrument]
+fn request_id_callee() -> Result<Option<String>, String> {
+    Ok(baggage_request_id())
+}
+
+#[instrument(skip(req_id), request_id = req_id)]
+fn request_id_handler(req_id: u64) -> Result<Option<String>, String> {
+    request_id_callee()
+}
+
+#[instrument(skip(req_id), request_id = req_id)]
+async fn request_id_handler_async(req_id: &str) -> Result<Option<String>, String> {
+    Ok(baggage_request_id())
+}
+
+#[tokio::test]
+async fn test_request_id() {
+    exporter();
+    assert_eq!(request_id_handler(42).unwrap().as_deref(), Some("42"));
+    let span = finished_span("request_id_handler");
+    assert_eq!(attribute(&span, "request.id"), Some(Value::from("42")));
+    assert_eq!(attribute(&span, "req_id"), None);
+
+    assert_eq!(
+        request_id_handler_async("req-1").await.unwrap().as_deref(),
+        Some("req-1")
+    );
+    let span = finished_span("request_id_handler_async");
+    assert_eq!(attribute(&span, "request.id"), Some(Value::from("req-1")));
+
+    // The baggage is scoped to the instrumented function
+    assert_eq!(baggage_request_id(), None);
+
+    // Existing baggage entries are kept
+    {
+        use opentelemetry::baggage::BaggageExt;
+        let _guard = Context::current_with_baggage([KeyValue::new("tenant", "acme")]).attach();
+        assert_eq!(request_id_handler(7).unwrap().as_deref(), Some("7"));
+        #[instrument(skip_all, request_id = 8)]
+        fn request_id_tenant() -> Result<Option<String>, String> {
+            Ok(Context::current()
+                .baggage()
+                .get("tenant")
+                .map(|tenant| tenant.to_string()))
+        }
+        assert_eq!(request_id_tenant().unwrap().as_deref(), Some("acme"));
+    }
+}
+
+mod resource {
+    use opentelemetry::{Key, Value};
+    use opentelemetry_sdk::Resource;
+    use opentelemetry_sdk::trace::SdkTracerProvider;
+    use otel_instrument::tracer_name;
+
+    tracer_name!(
+        "otel-instrument-resource-tests",
+        resource(deployment.environment = "prod", service.instance.id = 7)
+    );
+
+    #[test]
+    fn test_resource_attributes() {
+        let resource = Resource::builder_empty()
+            .with_attributes(_otel::resource_attributes())
+            .build();
+        assert_eq!(
+            resource.get(&Key::from_static_str("deployment.environment")),
+            Some(Value::from("prod"))
+        );
+        assert_eq!(
+            resource.get(&Key::from_static_str("service.instance.id")),
+            Some(Value::I64(7))
+        );
+
+        let provider = SdkTracerProvider::builder().with_resource(resource).build();
+        provider.shutdown().unwrap();
+    }
+}
+
+#[instrument(err)]
+fn early_return_sync(input: &str) -> Result<u32, std::num::ParseIntError> {
+    if input.is_empty() {
+        return Ok(0);
+    }
+    let value: u32 = input.parse()?;
+    if value > 100 {
+        return Ok(100);
+    }
+    Ok(value * 2)
+}
+
+#[instrument(err)]
+async fn early_return_async(input: &str) -> Result<u32, std::num::ParseIntError> {
+    if input.is_empty() {
+        return Ok(0);
+    }
+    let value: u32 = async { input.parse() }.await?;
+    if value > 100 {
+        return Ok(100);
+    }
+    Ok(value * 2)
+}
+
+#[tokio::test]
+async fn test_early_return_and_question_mark() {
+    exporter();
+    for (input, expected) in [
+        ("", Some(0)),
+        ("500", Some(100)),
+        ("21", Some(42)),
+        ("x", None),
+    ] {
+        assert_eq!(early_return_sync(input).ok(), expected);
+        assert_eq!(early_return_async(input).await.ok(), expected);
+    }
+
+    let spans = exporter().get_finished_spans().unwrap();
+    for name in ["early_return_sync", "early_return_async"] {
+        let statuses: Vec<_> = spans
+            .iter()
+            .filter(|span| span.name == name)
+            .map(|span| span.status.clone())
+            .collect();
+        assert_eq!(statuses.len(), 4);
+        assert_eq!(statuses[..3], [Status::Ok, Status::Ok, Status::Ok]);
+        assert!(matches!(statuses[3], Status::Error { .. }));
+    }
+}
+
+#[instrument(has_parent)]
+fn has_parent_root() -> Result<(), String> {
+    has_parent_nested()
+}
+
+#[instrument(has_parent)]
+fn has_parent_nested() -> Result<(), String> {
+    Ok(())
+}
+
+#[test]
+fn test_has_parent() {
+    exporter();
+    has_parent_root().unwrap();
+    let root = finished_span("has_parent_root");
+    assert_eq!(attribute(&root, "has_parent"), Some(Value::Bool(false)));
+    let nested = finished_span("has_parent_nested");
+    assert_eq!(attribute(&nested, "has_parent"), Some(Value::Bool(true)));
+    assert_eq!(nested.parent_span_id, root.span_context.span_id());
+}
+
+#[instrument(slo_ms = 10)]
+fn slo_slow() -> Result<(), String> {
+    std::thread::sleep(std::time::Duration::from_millis(30));
+    Ok(())
+}
+
+#[instrument(slo_ms = 60_000)]
+async fn slo_fast() -> Result<(), String> {
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_slo() {
+    exporter();
+    slo_slow().unwrap();
+    slo_fast().await.unwrap();
+    let slow = finished_span("slo_slow");
+    assert_eq!(attribute(&slow, "slo.exceeded"), Some(Value::Bool(true)));
+    assert_eq!(slow.status, Status::Ok);
+    let fast = finished_span("slo_fast");
+    assert_eq!(attribute(&fast, "slo.exceeded"), Some(Value::Bool(false)));
+}
+
+#[instrument(stream)]
+fn numbers_stream(count: u32) -> impl futures_util::Stream<Item = (u32, SpanId)> {
+    use futures_util::StreamExt;
+    futures_util::stream::iter(0..count)
+        .map(|i| (i, Context::current().span().span_context().span_id()))
+}
+
+#[tokio::test]
+async fn test_stream() {
+    use futures_util::StreamExt;
+    exporter();
+    let items: Vec<_> = numbers_stream(3).collect().await;
+    let span = finished_span("numbers_stream");
+    assert_eq!(attribute(&span, "count"), Some(Value::I64(3)));
+    assert_eq!(
+        items,
+        (0..3)
+            .map(|i| (i, span.span_context.span_id()))
+            .collect::<Vec<_>>()
+    );
+    // The span isn't active outside of polling the stream
+    assert!(!Context::current().has_active_span());
+}
+
+#[instrument(expose_ids = ids)]
+fn expose_ids_sync(ids: &mut opentelemetry::trace::SpanContext) -> Result<(), String> {
+    Ok(())
+}
+
+#[instrument(expose_ids = ids)]
+async fn expose_ids_async(
+    ids: &mut Option<opentelemetry::trace::SpanContext>,
+) -> Result<(), String> {
+    // The ids are available before the body runs
+    assert!(ids.is_some());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_expose_ids() {
+    exporter();
+    let mut ids = opentelemetry::trace::SpanContext::empty_context();
+    expose_ids_sync(&mut ids).unwrap();
+    let span = finished_span("expose_ids_sync");
+    assert!(ids.is_valid());
+    assert_eq!(ids.trace_id(), span.span_context.trace_id());
+    assert_eq!(ids.span_id(), span.span_context.span_id());
+    assert_eq!(attribute(&span, "ids"), None);
+
+    let mut ids = None;
+    expose_ids_async(&mut ids).await.unwrap();
+    let span = finished_span("expose_ids_async");
+    assert_eq!(ids.unwrap().span_id(), span.span_context.span_id());
+}
+
+static LAZY_FIELD_CALLS: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+
+fn expensive_dump(items: &[u32]) -> String {
+    LAZY_FIELD_CALLS.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+    format!("{items:?}")
+}
+
+#[instrument(skip_all, parent = parent, fields(dump = || expensive_dump(items)))]
+fn lazy_field(parent: &Context, items: &[u32]) -> Result<(), String> {
+    Ok(())
+}
+
+#[test]
+fn test_lazy_field() {
+    use opentelemetry::trace::{SpanContext, TraceFlags, TraceId, TraceState};
+    exporter();
+
+    // The default sampler follows the parent, so an unsampled parent disables recording
+    let unsampled = Context::new().with_remote_span_context(SpanContext::new(
+        TraceId::from(1),
+        SpanId::from(1),
+        TraceFlags::default(),
+        true,
+        TraceState::default(),
+    ));
+    lazy_field(&unsampled, &[1, 2]).unwrap();
+    assert_eq!(
+        LAZY_FIELD_CALLS.load(std::sync::atomic::Ordering::SeqCst),
+        0
+    );
+
+    lazy_field(&Context::new(), &[1, 2]).unwrap();
+    assert_eq!(
+        LAZY_FIELD_CALLS.load(std::sync::atomic::Ordering::SeqCst),
+        1
+    );
+    let span = finished_span("lazy_field");
+    assert_eq!(attribute(&span, "dump"), Some(Value::from("[1, 2]")));
+}
+
+#[instrument(skip(header), parent = _otel::traceparent(header))]
+fn traceparent_child(header: &str, id: u32) -> Result<(), String> {
+    Ok(())
+}
+
+#[test]
+fn test_traceparent_parent() {
+    exporter();
+    traceparent_child("00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01", 1).unwrap();
+    traceparent_child("not-a-traceparent", 2).unwrap();
+    let spans: Vec<_> = exporter()
+        .get_finished_spans()
+        .unwrap()
+        .into_iter()
+        .filter(|span| span.name == "traceparent_child")
+        .collect();
+    let child = spans
+        .iter()
+        .find(|span| attribute(span, "id") == Some(Value::I64(1)))
+        .unwrap();
+    assert_eq!(
+        child.span_context.trace_id().to_string(),
+        "0af7651916cd43dd8448eb211c80319c"
+    );
+    assert_eq!(child.parent_span_id.to_string(), "b7ad6b7169203331");
+    let root = spans
+        .iter()
+        .find(|span| attribute(span, "id") == Some(Value::I64(2)))
+        .unwrap();
+    assert_eq!(root.parent_span_id, SpanId::INVALID);
+}
+
+#[test]
+fn test_traceparent_malformed() {
+    for header in [
+        "",
+        "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331",
+        "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01-extra",
+        "ff-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01",
+        "00-0AF7651916CD43DD8448EB211C80319C-b7ad6b7169203331-01",
+        "00-00000000000000000000000000000000-b7ad6b7169203331-01",
+        "00-0af7651916cd43dd8448eb211c80319c-0000000000000000-01",
+        "00-0af7651916cd43dd8448eb211c8031-b7ad6b7169203331-01",
+        "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-0x",
+    ] {
+        assert!(!_otel::traceparent(header).has_active_span(), "{header}");
+    }
+
+    let ctx = _otel::traceparent("01-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-00-future");
+    let span = ctx.span();
+    let span_context = span.span_context();
+    assert!(span_context.is_remote());
+    assert!(!span_context.is_sampled());
+    assert_eq!(span_context.span_id().to_string(), "b7ad6b7169203331");
+}
+
+fn active_span_id() -> SpanId {
+    Context::current().span().span_context().span_id()
+}
+
+#[instrument]
+fn nested_sync_outer() -> Result<(SpanId, SpanId, SpanId), String> {
+    let before = active_span_id();
+    let inner = nested_sync_inner()?;
+    assert_ne!(inner, before);
+    Ok((before, inner, active_span_id()))
+}
+
+#[instrument]
+fn nested_sync_inner() -> Result<SpanId, String> {
+    Ok(active_span_id())
+}
+
+#[test]
+fn test_nested_sync_restores_active_span() {
+    exporter();
+    let tracer = global::tracer("test-tracer");
+    let ambient = Context::current_with_span(tracer.start("nested_sync_ambient"));
+    let _guard = ambient.clone().attach();
+    let ambient_id = active_span_id();
+
+    let (before, inner, after) = nested_sync_outer().unwrap();
+    let outer = finished_span("nested_sync_outer");
+    assert_eq!(before, outer.span_context.span_id());
+    assert_eq!(
+        inner,
+        finished_span("nested_sync_inner").span_context.span_id()
+    );
+    // The outer span is active again once the inner call returns
+    assert_eq!(after, before);
+    // And the ambient span once the outer call returns
+    assert_eq!(active_span_id(), ambient_id);
+}
+
+#[instrument(build_info)]
+fn build_info_default() -> Result<(), String> {
+    Ok(())
+}
+
+#[test]
+fn test_build_info_default() {
+    exporter();
+    build_info_default().unwrap();
+    let span = finished_span("build_info_default");
+    // Set for the tests by the build script
+    assert_eq!(
+        attribute(&span, "build.commit"),
+        Some(Value::from("9c1e0d7a2b4f6e8d0c1a3b5d7f9e1c2a4b6d8f0e"))
+    );
+    assert_eq!(
+        attribute(&span, "build.time"),
+        Some(Value::from("2026-02-03T04:05:06Z"))
+    );
+}
+
+mod build_info {
+    use super::{attribute, exporter, finished_span};
+    use opentelemetry::Value;
+    use otel_instrument::{instrument, tracer_name};
+
+    tracer_name!(
+        "otel-instrument-build-tests",
+        build(commit = "4f2a9c1", time = "2026-01-01T00:00:00Z")
+    );
+
+    #[instrument(build_info)]
+    fn build_info_override() -> Result<(), String> {
+        Ok(())
+    }
+
+    #[test]
+    fn test_build_info_override() {
+        exporter();
+        build_info_override().unwrap();
+        let span = finished_span("build_info_override");
+        assert_eq!(
+            attribute(&span, "build.commit"),
+            Some(Value::from("4f2a9c1"))
+        );
+        assert_eq!(
+            attribute(&span, "build.time"),
+            Some(Value::from("2026-01-01T00:00:00Z"))
+        );
+    }
+}
+
+#[instrument(err, ret)]
+fn early_return_err_sync(mode: u8) -> Result<&'static str, FetchError> {
+    if mode == 0 {
+        return Err(FetchError::NotFound);
+    }
+    if mode == 1 {
+        return Ok("early");
+    }
+    Ok("late")
+}
+
+#[instrument(err, ret)]
+async fn early_return_err_async(mode: u8) -> Result<&'static str, FetchError> {
+    if mode == 0 {
+        return Err(FetchError::NotFound);
+    }
+    if mode == 1 {
+        return Ok("early");
+    }
+    Ok("late")
+}
+
+#[tokio::test]
+async fn test_early_return_status() {
+    exporter();
+    for mode in 0..3 {
+        let expected = [None, Some("early"), Some("late")][mode as usize];
+        assert_eq!(early_return_err_sync(mode).ok(), expected);
+        assert_eq!(early_return_err_async(mode).await.ok(), expected);
+    }
+
+    let spans = exporter().get_finished_spans().unwrap();
+    for name in ["early_return_err_sync", "early_return_err_async"] {
+        let spans: Vec<_> = spans.iter().filter(|span| span.name == name).collect();
+        assert_eq!(spans.len(), 3);
+        assert_eq!(spans[0].status, Status::error("NotFound"));
+        assert_eq!(attribute(spans[0], "error"), Some(Value::from("NotFound")));
+        assert_eq!(spans[0].events.len(), 1);
+        assert_eq!(spans[1].status, Status::Ok);
+        assert_eq!(
+            attribute(spans[1], "return"),
+            Some(Value::from("\"early\""))
+        );
+        assert_eq!(spans[2].status, Status::Ok);
+        assert_eq!(attribute(spans[2], "return"), Some(Value::from("\"late\"")));
+    }
+}
+
+#[instrument(skip_all, fields(blob = truncate(blob, 8), full = blob, short = truncate("ñañañ", 3)))]
+fn truncated_field(blob: &str) -> Result<(), String> {
+    Ok(())
+}
+
+#[test]
+fn test_truncated_field() {
+    exporter();
+    truncated_field("0123456789abcdef").unwrap();
+    let span = finished_span("truncated_field");
+    assert_eq!(attribute(&span, "blob"), Some(Value::from("01234567")));
+    assert_eq!(
+        attribute(&span, "full"),
+        Some(Value::from("0123456789abcdef"))
+    );
+    // Truncation respects character boundaries
+    assert_eq!(attribute(&span, "short"), Some(Value::from("ñañ")));
+}
+
+#[instrument(record_async)]
+fn record_async_sync() -> Result<(), String> {
+    Ok(())
+}
+
+#[instrument(record_async)]
+async fn record_async_async() -> Result<(), String> {
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_record_async() {
+    exporter();
+    record_async_sync().unwrap();
+    record_async_async().await.unwrap();
+    let span = finished_span("record_async_sync");
+    assert_eq!(attribute(&span, "code.async"), Some(Value::Bool(false)));
+    let span = finished_span("record_async_async");
+    assert_eq!(attribute(&span, "code.async"), Some(Value::Bool(true)));
+}
+
+mod result_alias {
+    // Pulls in the items generated by `tracer_name!` in the parent module
+    use super::*;
+
+    type Result<T> = std::result::Result<T, FetchError>;
+
+    #[instrument(ret, err)]
+    fn alias_value(found: bool) -> Result<u32> {
+        if found {
+            Ok(7)
+        } else {
+            Err(FetchError::NotFound)
+        }
+    }
+
+    #[instrument(ret, err)]
+    async fn alias_unit() -> Result<()> {
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_result_alias() {
+        exporter();
+        alias_value(true).unwrap();
+        alias_value(false).unwrap_err();
+        alias_unit().await.unwrap();
+
+        let spans = exporter().get_finished_spans().unwrap();
+        let spans: Vec<_> = spans
+            .iter()
+            .filter(|span| span.name == "alias_value")
+            .collect();
+        assert_eq!(attribute(spans[0], "return"), Some(Value::from("7")));
+        assert_eq!(spans[0].status, Status::Ok);
+        assert_eq!(spans[1].status, Status::error("NotFound"));
+        assert_eq!(spans[1].events.len(), 1);
+
+        // Unit success values are skipped through the alias too
+        let span = finished_span("alias_unit");
+        assert_eq!(attribute(&span, "return"), None);
+        assert_eq!(span.status, Status::Ok);
+    }
+}
+
+// The variant fields only exist to show they aren't recorded
+#[allow(dead_code)]
+#[derive(Debug, otel_instrument::VariantName)]
+enum Event<T> {
+    Created { id: T, name: String },
+    Deleted(T),
+    Cleared,
+}
+
+#[instrument(skip(event), fields(kind = variant(event)))]
+fn handle_event(event: Event<u64>) -> Result<(), String> {
+    Ok(())
+}
+
+#[test]
+fn test_variant_field() {
+    exporter();
+    assert_eq!(Event::Deleted(1u8).variant_name(), "Deleted");
+    assert_eq!(Event::<u8>::Cleared.variant_name(), "Cleared");
+    handle_event(Event::Created {
+        id: 7,
+        name: "jane".into(),
+    })
+    .unwrap();
+    let span = finished_span("handle_event");
+    assert_eq!(attribute(&span, "kind"), Some(Value::from("Created")));
+}
+
+mod qualified {
+    use super::*;
+
+    #[instrument(qualified_name)]
+    pub(super) fn qualified_name_fn() -> Result<(), String> {
+        Ok(())
+    }
+
+    #[instrument(qualified_name, name = "explicit_over_qualified")]
+    pub(super) fn qualified_name_explicit() -> Result<(), String> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_qualified_name() {
+    exporter();
+    qualified::qualified_name_fn().unwrap();
+    qualified::qualified_name_explicit().unwrap();
+    finished_span("span_attributes::qualified::qualified_name_fn");
+    finished_span("explicit_over_qualified");
+}
+
+fn parse_parent(header: &str) -> Result<opentelemetry::trace::SpanContext, String> {
+    let ctx = _otel::traceparent(header);
+    let span = ctx.span();
+    let span_context = span.span_context();
+    if span_context.is_valid() {
+        Ok(span_context.clone())
+    } else {
+        Err(format!("invalid traceparent `{header}`"))
+    }
+}
+
+#[instrument(skip(header), parent = parse_parent(header))]
+fn fallible_parent(header: &str, id: u32) -> Result<u32, String> {
+    Ok(id)
+}
+
+#[instrument(skip(parent), parent = parent)]
+fn optional_parent(parent: Option<Context>) -> Result<(), String> {
+    Ok(())
+}
+
+#[test]
+fn test_fallible_parent() {
+    exporter();
+    assert_eq!(fallible_parent("garbage", 1).unwrap(), 1);
+    assert_eq!(
+        fallible_parent("00-0af7651916cd43dd8448eb211c80319d-b7ad6b7169203332-01", 2).unwrap(),
+        2
+    );
+    let spans = exporter().get_finished_spans().unwrap();
+    let fallback = spans
+        .iter()
+        .find(|span| span.name == "fallible_parent" && attribute(span, "id") == Some(Value::I64(1)))
+        .unwrap();
+    assert_eq!(fallback.parent_span_id, SpanId::INVALID);
+    let child = spans
+        .iter()
+        .find(|span| span.name == "fallible_parent" && attribute(span, "id") == Some(Value::I64(2)))
+        .unwrap();
+    assert_eq!(child.parent_span_id.to_string(), "b7ad6b7169203332");
+
+    // A missing parent falls back to the caller's active span
+    let tracer = global::tracer("test-tracer");
+    let ambient = Context::current_with_span(tracer.start("optional_parent_ambient"));
+    let ambient_id = ambient.span().span_context().span_id();
+    {
+        let _guard = ambient.attach();
+        optional_parent(None).unwrap();
+    }
+    assert_eq!(finished_span("optional_parent").parent_span_id, ambient_id);
+}
+
+mod default_fields {
+    use super::{attribute, exporter, finished_span};
+    use opentelemetry::Value;
+    use otel_instrument::{instrument, tracer_name};
+
+    tracer_name!(
+        "otel-instrument-default-fields-tests",
+        default_fields(service.version = "1.0", deployment.environment = "test")
+    );
+
+    #[instrument]
+    fn default_fields_fn(id: u32) -> Result<(), String> {
+        Ok(())
+    }
+
+    #[test]
+    fn test_default_fields() {
+        exporter();
+        default_fields_fn(1).unwrap();
+        let span = finished_span("default_fields_fn");
+        assert_eq!(
+            attribute(&span, "service.version"),
+            Some(Value::from("1.0"))
+        );
+        assert_eq!(
+            attribute(&span, "deployment.environment"),
+            Some(Value::from("test"))
+        );
+        assert_eq!(attribute(&span, "id"), Some(Value::I64(1)));
+    }
+}
+
+// Deprecated in favor of `fields(ok = outcome.is_ok())`, but still supported
+#[instrument(record_ok)]
+fn record_ok_outcome(succeed: bool) -> Result<(), String> {
+    if succeed {
+        Ok(())
+    } else {
+        Err("failed".into())
+    }
+}
+
+#[instrument(err, record_ok)]
+async fn record_ok_outcome_async(succeed: bool) -> Result<(), FetchError> {
+    if succeed {
+        Ok(())
+    } else {
+        Err(FetchError::Timeout)
+    }
+}
+
+#[tokio::test]
+async fn test_record_ok() {
+    exporter();
+    record_ok_outcome(true).unwrap();
+    record_ok_outcome(false).unwrap_err();
+    record_ok_outcome_async(true).await.unwrap();
+    record_ok_outcome_async(false).await.unwrap_err();
+
+    let spans = exporter().get_finished_spans().unwrap();
+    for name in ["record_ok_outcome", "record_ok_outcome_async"] {
+        let outcomes: Vec<_> = spans
+            .iter()
+            .filter(|span| span.name == name)
+            .map(|span| (attribute(span, "succeed"), attribute(span, "ok")))
+            .collect();
+        assert_eq!(
+            outcomes,
+            [
+                (Some(Value::Bool(true)), Some(Value::Bool(true))),
+                (Some(Value::Bool(false)), Some(Value::Bool(false))),
+            ]
+        );
+    }
+}
+
+struct Point {
+    x: i32,
+    y: i32,
+}
+
+#[instrument]
+fn tuple_pattern((a, b): (i32, i32), Point { x, y: renamed }: Point) -> Result<i32, String> {
+    Ok(a + b + x + renamed)
+}
+
+#[instrument(skip(b))]
+async fn tuple_pattern_async((a, b): (i32, i32), _: u8) -> Result<i32, String> {
+    Ok(a * b)
+}
+
+#[tokio::test]
+async fn test_tuple_pattern() {
+    exporter();
+    assert_eq!(tuple_pattern((1, 2), Point { x: 3, y: 4 }).unwrap(), 10);
+    let span = finished_span("tuple_pattern");
+    assert_eq!(attribute(&span, "a"), Some(Value::I64(1)));
+    assert_eq!(attribute(&span, "b"), Some(Value::I64(2)));
+    assert_eq!(attribute(&span, "x"), Some(Value::I64(3)));
+    assert_eq!(attribute(&span, "renamed"), Some(Value::I64(4)));
+    assert_eq!(attribute(&span, "y"), None);
+
+    assert_eq!(tuple_pattern_async((3, 4), 0).await.unwrap(), 12);
+    let span = finished_span("tuple_pattern_async");
+    assert_eq!(attribute(&span, "a"), Some(Value::I64(3)));
+    assert_eq!(attribute(&span, "b"), None);
+}
+
+#[instrument(ret(variant))]
+fn ret_variant(running: bool) -> Result<Event<u64>, String> {
+    Ok(if running {
+        Event::Cleared
+    } else {
+        Event::Created {
+            id: 1,
+            name: "job".into(),
+        }
+    })
+}
+
+#[instrument(ret(variant, debug = .variant_name().len()))]
+async fn ret_variant_projection() -> Result<Event<u64>, String> {
+    Ok(Event::Deleted(9))
+}
+
+#[tokio::test]
+async fn test_ret_variant() {
+    exporter();
+    ret_variant(false).unwrap();
+    let span = finished_span("ret_variant");
+    assert_eq!(attribute(&span, "return"), Some(Value::from("Created")));
+
+    ret_variant_projection().await.unwrap();
+    let span = finished_span("ret_variant_projection");
+    assert_eq!(attribute(&span, "return"), Some(Value::from("Deleted")));
+    assert_eq!(attribute(&span, "debug"), Some(Value::from("7")));
+}
+
+#[instrument(
+    skip(a),
+    fields(x = 1),
+    name = "repeated_lists",
+    skip(b),
+    fields(y = 2)
+)]
+fn repeated_lists(a: u32, b: u32, c: u32) -> Result<(), String> {
+    let _ = (a, b, c);
+    Ok(())
+}
+
+#[test]
+fn test_repeated_lists() {
+    exporter();
+    repeated_lists(1, 2, 3).unwrap();
+    let span = finished_span("repeated_lists");
+    assert_eq!(attribute(&span, "a"), None);
+    assert_eq!(attribute(&span, "b"), None);
+    assert_eq!(attribute(&span, "c"), Some(Value::I64(3)));
+    assert_eq!(attribute(&span, "x"), Some(Value::I64(1)));
+    assert_eq!(attribute(&span, "y"), Some(Value::I64(2)));
+}
+
+// Stands in for a tracer provider, noting whether the span had already been
+// exported when it was shut down
+struct ShutdownProbe;
+
+static SHUTDOWN_AFTER_SPAN: std::sync::atomic::AtomicBool =
+    std::sync::atomic::AtomicBool::new(false);
+
+impl ShutdownProbe {
+    fn shutdown(&self) -> Result<(), String> {
+        let exported = exporter()
+            .get_finished_spans()
+            .unwrap()
+            .iter()
+            .any(|span| span.name == "root_main");
+        SHUTDOWN_AFTER_SPAN.store(exported, std::sync::atomic::Ordering::SeqCst);
+        Ok(())
+    }
+}
+
+#[instrument(root = ShutdownProbe)]
+fn root_main() -> Result<(), String> {
+    Ok(())
+}
+
+#[test]
+fn test_root() {
+    exporter();
+    let tracer = global::tracer("test-tracer");
+    let parent_ctx = Context::current_with_span(tracer.start("root_ambient_parent"));
+    let _guard = parent_ctx.attach();
+
+    root_main().unwrap();
+    assert!(SHUTDOWN_AFTER_SPAN.load(std::sync::atomic::Ordering::SeqCst));
+    let span = finished_span("root_main");
+    assert_eq!(span.parent_span_id, SpanId::INVALID);
+}
+
+#[instrument(namespace_attrs, skip(password), fields(method = "password"))]
+fn login(username: &str, password: &str) -> Result<(), String> {
+    let _ = password;
+    Ok(())
+}
+
+#[test]
+fn test_namespace_attrs() {
+    exporter();
+    login("jane", "hunter2").unwrap();
+    let span = finished_span("login");
+    assert_eq!(
+        attribute(&span, "login.username"),
+        Some(Value::from("jane"))
+    );
+    assert_eq!(
+        attribute(&span, "login.method"),
+        Some(Value::from("password"))
+    );
+    assert_eq!(attribute(&span, "username"), None);
+    assert_eq!(attribute(&span, "login.password"), None);
+}
+
+struct Countdown(u32);
+
+impl Countdown {
+    #[instrument(skip(cx))]
+    fn poll_countdown(&mut self, cx: &mut std::task::Context<'_>) -> std::task::Poll<u32> {
+        if self.0 == 0 {
+            return std::task::Poll::Ready(7);
+        }
+        self.0 -= 1;
+        cx.waker().wake_by_ref();
+        std::task::Poll::Pending
+    }
+}
+
+#[test]
+fn test_poll() {
+    exporter();
+    let mut countdown = Countdown(1);
+    let mut cx = std::task::Context::from_waker(std::task::Waker::noop());
+    assert!(countdown.poll_countdown(&mut cx).is_pending());
+    assert_eq!(countdown.poll_countdown(&mut cx), std::task::Poll::Ready(7));
+
+    let spans: Vec<_> = exporter()
+        .get_finished_spans()
+        .unwrap()
+        .into_iter()
+        .filter(|span| span.name == "poll_countdown")
+        .collect();
+    assert_eq!(spans.len(), 2);
+    assert_eq!(attribute(&spans[0], "poll.ready"), Some(Value::Bool(false)));
+    assert_eq!(attribute(&spans[1], "poll.ready"), Some(Value::Bool(true)));
+    assert!(spans.iter().all(|span| span.status == Status::Unset));
+}
+
+struct Delay(u32);
+
+#[instrument_impl]
+impl std::future::Future for Delay {
+    type Output = u32;
+
+    #[instrument(name = "delay_poll", skip(cx))]
+    fn poll(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Self::Output> {
+        if self.0 == 0 {
+            return std::task::Poll::Ready(7);
+        }
+        self.0 -= 1;
+        cx.waker().wake_by_ref();
+        std::task::Poll::Pending
+    }
+}
+
+#[tokio::test]
+async fn test_future_poll() {
+    exporter();
+    assert_eq!(Delay(2).await, 7);
+
+    // Every poll gets its own span, until the future completes
+    let spans: Vec<_> = exporter()
+        .get_finished_spans()
+        .unwrap()
+        .into_iter()
+        .filter(|span| span.name == "delay_poll")
+        .collect();
+    assert_eq!(spans.len(), 3);
+    assert_eq!(attribute(&spans[0], "poll.ready"), Some(Value::Bool(false)));
+    assert_eq!(attribute(&spans[1], "poll.ready"), Some(Value::Bool(false)));
+    assert_eq!(attribute(&spans[2], "poll.ready"), Some(Value::Bool(true)));
+    assert!(spans.iter().all(|span| span.status == Status::Unset));
+}
+
+#[instrument(param_names, skip(password))]
+fn param_names_login(username: &str, password: &str) -> Result<(), String> {
+    let _ = (username, password);
+    Ok(())
+}
+
+#[test]
+fn test_param_names() {
+    exporter();
+    param_names_login("jane", "hunter2").unwrap();
+    let span = finished_span("param_names_login");
+    assert_eq!(
+        attribute(&span, "code.parameters"),
+        Some(Value::from("username,password"))
+    );
+}
+
+#[instrument(ret)]
+fn ret_impl_display() -> Result<impl std::fmt::Display, String> {
+    Ok("ready")
+}
+
+// Warns that the success value isn't recorded
+#[allow(deprecated)]
+#[instrument(ret)]
+fn ret_impl_iterator() -> Result<impl Iterator<Item = u32>, String> {
+    Ok(0..3)
+}
+
+#[instrument(ret(Display))]
+fn ret_display_string() -> Result<String, String> {
+    Ok("jane".to_string())
+}
+
+#[test]
+fn test_ret_impl_trait() {
+    exporter();
+    assert_eq!(ret_impl_display().unwrap().to_string(), "ready");
+    let span = finished_span("ret_impl_display");
+    assert_eq!(attribute(&span, "return"), Some(Value::from("ready")));
+
+    // Without a formatting bound the value can't be recorded
+    assert_eq!(ret_impl_iterator().unwrap().sum::<u32>(), 3);
+    let span = finished_span("ret_impl_iterator");
+    assert_eq!(attribute(&span, "return"), None);
+    assert_eq!(span.status, Status::Ok);
+
+    ret_display_string().unwrap();
+    let span = finished_span("ret_display_string");
+    assert_eq!(attribute(&span, "return"), Some(Value::from("jane")));
+}
+
+#[instrument(context_scoped = cx)]
+fn context_scoped_parent() -> Result<(), String> {
+    std::thread::spawn(move || {
+        let _guard = cx.attach();
+        context_scoped_child()
+    })
+    .join()
+    .unwrap()
+}
+
+#[instrument]
+fn context_scoped_child() -> Result<(), String> {
+    Ok(())
+}
+
+#[test]
+fn test_context_scoped() {
+    exporter();
+    context_scoped_parent().unwrap();
+    let parent = finished_span("context_scoped_parent");
+    let child = finished_span("context_scoped_child");
+    assert_eq!(child.parent_span_id, parent.span_context.span_id());
+    assert_eq!(
+        child.span_context.trace_id(),
+        parent.span_context.trace_id()
+    );
+}
+
+#[instrument(ret(compact))]
+fn ret_compact_small() -> Result<u32, String> {
+    Ok(7)
+}
+
+#[instrument(ret(compact = 8))]
+fn ret_compact_large() -> Result<Vec<u32>, String> {
+    Ok((0..100).collect())
+}
+
+#[instrument(ret(compact))]
+fn ret_compact_error() -> Result<u32, String> {
+    Err("failed".into())
+}
+
+#[test]
+fn test_ret_compact() {
+    exporter();
+    ret_compact_small().unwrap();
+    let span = finished_span("ret_compact_small");
+    assert_eq!(attribute(&span, "return.ok"), Some(Value::Bool(true)));
+    assert_eq!(attribute(&span, "return.value"), Some(Value::from("7")));
+
+    ret_compact_large().unwrap();
+    let span = finished_span("ret_compact_large");
+    assert_eq!(attribute(&span, "return.ok"), Some(Value::Bool(true)));
+    assert_eq!(attribute(&span, "return.value"), None);
+
+    ret_compact_error().unwrap_err();
+    let span = finished_span("ret_compact_error");
+    assert_eq!(attribute(&span, "return.ok"), Some(Value::Bool(false)));
+    assert_eq!(attribute(&span, "return.value"), None);
+}
+
+mod schema_url {
+    use super::{exporter, finished_span};
+    use otel_instrument::{instrument, tracer_name};
+
+    tracer_name!(
+        "otel-instrument-schema-tests",
+        schema_url = "https://opentelemetry.io/schemas/1.25.0"
+    );
+
+    #[instrument]
+    fn schema_url_scope() -> Result<(), String> {
+        Ok(())
+    }
+
+    #[test]
+    fn test_schema_url() {
+        exporter();
+        schema_url_scope().unwrap();
+        let span = finished_span("schema_url_scope");
+        assert_eq!(
+            span.instrumentation_scope.name(),
+            "otel-instrument-schema-tests"
+        );
+        assert_eq!(
+            span.instrumentation_scope.schema_url(),
+            Some("https://opentelemetry.io/schemas/1.25.0")
+        );
+    }
+}
+
+#[instrument]
+async fn checkpoints() -> Result<(), String> {
+    std::thread::sleep(std::time::Duration::from_millis(5));
+    checkpoint!("db_query");
+    checkpoint!("render");
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_checkpoint() {
+    exporter();
+    checkpoints().await.unwrap();
+    let span = finished_span("checkpoints");
+    let events: Vec<_> = span
+        .events
+        .iter()
+        .map(|event| {
+            let elapsed_ms = event
+                .attributes
+                .iter()
+                .find(|kv| kv.key.as_str() == "elapsed_ms")
+                .map(|kv| match kv.value {
+                    Value::F64(elapsed_ms) => elapsed_ms,
+                    ref value => panic!("unexpected elapsed_ms {value:?}"),
+                })
+                .unwrap();
+            (event.name.to_string(), elapsed_ms)
+        })
+        .collect();
+    assert_eq!(events.len(), 2);
+    assert_eq!(events[0].0, "db_query");
+    assert!(events[0].1 >= 5.0);
+    // The second checkpoint is timed from the first one
+    assert_eq!(events[1].0, "render");
+    assert!(events[1].1 < events[0].1);
+}
+
+#[instrument(name = if fast { "computed_name_fast" } else { "computed_name_slow" })]
+fn computed_name(fast: bool) -> Result<(), String> {
+    Ok(())
+}
+
+#[instrument(name = format!("computed_name_{id}"))]
+fn computed_name_owned(id: u32) -> Result<(), String> {
+    Ok(())
+}
+
+#[test]
+fn test_computed_name() {
+    exporter();
+    computed_name(true).unwrap();
+    computed_name(false).unwrap();
+    computed_name_owned(3).unwrap();
+    let fast = finished_span("computed_name_fast");
+    assert_eq!(attribute(&fast, "fast"), Some(Value::Bool(true)));
+    let slow = finished_span("computed_name_slow");
+    assert_eq!(attribute(&slow, "fast"), Some(Value::Bool(false)));
+    finished_span("computed_name_3");
+}
+
+#[instrument(fields(pod = env("OTEL_INSTRUMENT_TEST_POD"), missing = env("OTEL_INSTRUMENT_TEST_UNSET")))]
+fn env_fields() -> Result<(), String> {
+    Ok(())
+}
+
+#[test]
+fn test_env_fields() {
+    exporter();
+    // SAFETY: no other test reads or writes this variable
+    unsafe { std::env::set_var("OTEL_INSTRUMENT_TEST_POD", "api-7f9c") };
+    env_fields().unwrap();
+    let span = finished_span("env_fields");
+    assert_eq!(attribute(&span, "pod"), Some(Value::from("api-7f9c")));
+    assert_eq!(attribute(&span, "missing"), Some(Value::from("")));
+}
+
+#[instrument(baggage_attrs(tenant_id, request.id, missing))]
+fn baggage_attrs_child() -> Result<(), String> {
+    Ok(())
+}
+
+#[test]
+fn test_baggage_attrs() {
+    use opentelemetry::baggage::BaggageExt;
+
+    exporter();
+    let tracer = global::tracer("test-tracer");
+    let parent_ctx =
+        Context::current_with_span(tracer.start("baggage_attrs_parent")).with_baggage([
+            KeyValue::new("tenant_id", "acme"),
+            KeyValue::new("request.id", "req-9"),
+        ]);
+    {
+        let _guard = parent_ctx.attach();
+        baggage_attrs_child().unwrap();
+    }
+    let span = finished_span("baggage_attrs_child");
+    assert_eq!(attribute(&span, "tenant_id"), Some(Value::from("acme")));
+    assert_eq!(attribute(&span, "request.id"), Some(Value::from("req-9")));
+    assert_eq!(attribute(&span, "missing"), None);
+}
+
+#[instrument]
+fn mutated_param(items: &mut Vec<i32>, count: &mut u32) -> Result<(), String> {
+    items.push(4);
+    *count += 1;
+    Ok(())
+}
+
+#[test]
+fn test_mutable_params_recorded_before_call() {
+    exporter();
+    let mut items = vec![1, 2, 3];
+    let mut count = 7;
+    mutated_param(&mut items, &mut count).unwrap();
+    assert_eq!(items, [1, 2, 3, 4]);
+    assert_eq!(count, 8);
+    let span = finished_span("mutated_param");
+    assert_eq!(attribute(&span, "items"), Some(Value::from("[1, 2, 3]")));
+    assert_eq!(attribute(&span, "count"), Some(Value::I64(7)));
+}
+
+#[instrument(ret(len))]
+fn ret_len_names() -> Result<Vec<String>, String> {
+    Ok(vec!["jane".into(), "john".into(), "joan".into()])
+}
+
+#[test]
+fn test_ret_len() {
+    exporter();
+    ret_len_names().unwrap();
+    let span = finished_span("ret_len_names");
+    assert_eq!(attribute(&span, "return.len"), Some(Value::I64(3)));
+    assert_eq!(attribute(&span, "return"), None);
+}
+
+mod scopes {
+    use super::{exporter, finished_span};
+    use otel_instrument::{instrument, tracer_name};
+
+    tracer_name!(
+        "otel-instrument-scope-tests",
+        db = "otel-instrument-scope-tests.db",
+        http = "otel-instrument-scope-tests.http"
+    );
+
+    #[instrument(tracer = db)]
+    fn scoped_query() -> Result<(), String> {
+        Ok(())
+    }
+
+    #[instrument(tracer = http)]
+    async fn scoped_handler() -> Result<(), String> {
+        scoped_query()
+    }
+
+    #[instrument]
+    fn scoped_default() -> Result<(), String> {
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_named_scopes() {
+        exporter();
+        scoped_handler().await.unwrap();
+        scoped_default().unwrap();
+        assert_eq!(
+            finished_span("scoped_query").instrumentation_scope.name(),
+            "otel-instrument-scope-tests.db"
+        );
+        assert_eq!(
+            finished_span("scoped_handler").instrumentation_scope.name(),
+            "otel-instrument-scope-tests.http"
+        );
+        assert_eq!(
+            finished_span("scoped_default").instrumentation_scope.name(),
+            "otel-instrument-scope-tests"
+        );
+    }
+}
+
+#[instrument(name = "", namespace_attrs)]
+fn empty_name_fallback(id: u32) -> Result<(), String> {
+    Ok(())
+}
+
+#[test]
+fn test_empty_name_fallback() {
+    exporter();
+    empty_name_fallback(1).unwrap();
+    let span = finished_span("empty_name_fallback");
+    assert_eq!(
+        attribute(&span, "empty_name_fallback.id"),
+        Some(Value::I64(1))
+    );
+}
+
+// Fields are only read through `Debug`
+#[allow(dead_code)]
+#[derive(Debug)]
+struct Snapshot(Vec<u8>);
+
+#[allow(dead_code)]
+#[derive(Debug)]
+struct Region(&'static str);
+
+impl _otel::Traceable for Region {}
+
+#[instrument(strict)]
+fn strict_params(user: &str, id: u64, region: Region, snapshot: &Snapshot) -> Result<(), String> {
+    let _ = (user, id, region, snapshot);
+    Ok(())
+}
+
+#[test]
+fn test_strict() {
+    exporter();
+    strict_params("jane", 7, Region("eu"), &Snapshot(vec![0; 1024])).unwrap();
+    let span = finished_span("strict_params");
+    assert_eq!(attribute(&span, "user"), Some(Value::from("jane")));
+    assert_eq!(attribute(&span, "id"), Some(Value::from("7")));
+    assert_eq!(
+        attribute(&span, "region"),
+        Some(Value::from("Region(\"eu\")"))
+    );
+    // Not `Traceable`, so it is skipped rather than dumped
+    assert_eq!(attribute(&span, "snapshot"), None);
+}
+
+#[instrument(min_duration_ms = 60_000)]
+fn min_duration_fast(id: u32) -> Result<(), String> {
+    Ok(())
+}
+
+#[instrument(min_duration_ms = 10, ret)]
+async fn min_duration_slow(id: u32) -> Result<u32, String> {
+    std::thread::sleep(std::time::Duration::from_millis(30));
+    Ok(id * 2)
+}
+
+#[tokio::test]
+async fn test_min_duration() {
+    exporter();
+    min_duration_fast(1).unwrap();
+    min_duration_slow(2).await.unwrap();
+    let spans = exporter().get_finished_spans().unwrap();
+    assert!(!spans.iter().any(|span| span.name == "min_duration_fast"));
+
+    let slow = finished_span("min_duration_slow");
+    assert_eq!(attribute(&slow, "id"), Some(Value::I64(2)));
+    assert_eq!(attribute(&slow, "return"), Some(Value::from("4")));
+    assert_eq!(slow.status, Status::Ok);
+    // Backdated to when the call began
+    let duration = slow.end_time.duration_since(slow.start_time).unwrap();
+    assert!(duration >= std::time::Duration::from_millis(30));
+}
+
+#[allow(dead_code)]
+#[derive(Debug)]
+struct PoolConfig {
+    size: u32,
+    host: &'static str,
+}
+
+#[instrument(skip(config), fields(config = pretty(config)))]
+fn pretty_field(config: &PoolConfig) -> Result<(), String> {
+    Ok(())
+}
+
+#[test]
+fn test_pretty_field() {
+    exporter();
+    pretty_field(&PoolConfig {
+        size: 4,
+        host: "db",
+    })
+    .unwrap();
+    let span = finished_span("pretty_field");
+    assert_eq!(
+        attribute(&span, "config"),
+        Some(Value::from(
+            "PoolConfig {\n    size: 4,\n    host: \"db\",\n}"
+        ))
+    );
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("upstream returned {0}")]
+struct UpstreamError(u16);
+
+impl UpstreamError {
+    fn code(&self) -> u16 {
+        self.0
+    }
+}
+
+#[instrument(err(code = e.code(), no_attr))]
+fn err_derived_attrs() -> Result<(), UpstreamError> {
+    Err(UpstreamError(503))
+}
+
+#[test]
+fn test_err_derived_attrs() {
+    exporter();
+    err_derived_attrs().unwrap_err();
+    let span = finished_span("err_derived_attrs");
+    assert_eq!(attribute(&span, "error.code"), Some(Value::I64(503)));
+    assert_eq!(attribute(&span, "error"), None);
+    assert_eq!(span.status, Status::error("UpstreamError(503)"));
+}
+
+// Outer attributes stay on the instrumented function, which is the only one
+// generated, so the lint allowance covers the whole expansion under clippy
+#[allow(clippy::too_many_arguments)]
+#[instrument(skip(f, g, h))]
+fn many_arguments(a: u8, b: u8, c: u8, d: u8, e: u8, f: u8, g: u8, h: u8) -> Result<u32, String> {
+    Ok([a, b, c, d, e, f, g, h].iter().map(|&n| n as u32).sum())
+}
+
+#[test]
+fn test_many_arguments() {
+    exporter();
+    assert_eq!(many_arguments(1, 2, 3, 4, 5, 6, 7, 8), Ok(36));
+    let span = finished_span("many_arguments");
+    assert_eq!(attribute(&span, "e"), Some(Value::I64(5)));
+    assert_eq!(attribute(&span, "h"), None);
+}
+
+#[instrument(ret, err = e)]
+fn ret_and_err(found: bool) -> Result<u32, FetchError> {
+    if found {
+        Ok(7)
+    } else {
+        Err(FetchError::NotFound)
+    }
+}
+
+#[test]
+fn test_ret_and_err() {
+    exporter();
+    ret_and_err(true).unwrap();
+    ret_and_err(false).unwrap_err();
+    let spans = exporter().get_finished_spans().unwrap();
+    let spans: Vec<_> = spans
+        .iter()
+        .filter(|span| span.name == "ret_and_err")
+        .collect();
+
+    // Success records the return value and the Ok status, without error details
+    assert_eq!(attribute(spans[0], "return"), Some(Value::from("7")));
+    assert_eq!(attribute(spans[0], "error"), None);
+    assert_eq!(spans[0].status, Status::Ok);
+    assert!(spans[0].events.is_empty());
+
+    // Failure only takes the error path
+    assert_eq!(attribute(spans[1], "return"), None);
+    assert_eq!(attribute(spans[1], "error"), Some(Value::from("NotFound")));
+    assert_eq!(spans[1].status, Status::error("NotFound"));
+    assert_eq!(spans[1].events.len(), 1);
+}
+
+struct Actor {
+    span_ctx: Context,
+    handled: u32,
+}
+
+impl Actor {
+    #[instrument(skip(self), parent = self.span_ctx)]
+    fn actor_handle(&mut self, message: u32) -> Result<u32, String> {
+        self.handled += message;
+        Ok(self.handled)
+    }
+
+    #[instrument(skip(self), parent = self.span_ctx)]
+    async fn actor_stop(self) -> Result<u32, String> {
+        Ok(self.handled)
+    }
+}
+
+#[tokio::test]
+async fn test_parent_from_self() {
+    exporter();
+    let parent_span = global::tracer("test-tracer").start("actor_parent");
+    let parent_span_id = parent_span.span_context().span_id();
+    let mut actor = Actor {
+        span_ctx: Context::current_with_span(parent_span),
+        handled: 0,
+    };
+    // The parent is read from `self` before the body borrows or consumes it
+    assert_eq!(actor.actor_handle(2), Ok(2));
+    assert_eq!(actor.actor_stop().await, Ok(2));
+
+    let handle = finished_span("actor_handle");
+    assert_eq!(handle.parent_span_id, parent_span_id);
+    assert_eq!(attribute(&handle, "message"), Some(Value::I64(2)));
+    let stop = finished_span("actor_stop");
+    assert_eq!(stop.parent_span_id, parent_span_id);
+}
+
+#[instrument(skip(labels, tags), fields(labels = spread(labels), tags = spread(tags, 2)))]
+fn spread_fields(
+    labels: &std::collections::HashMap<String, String>,
+    tags: std::collections::BTreeMap<&'static str, u32>,
+) -> Result<(), String> {
+    Ok(())
+}
+
+#[test]
+fn test_spread_fields() {
+    exporter();
+    let labels = [("env", "prod"), ("team", "core")]
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .into();
+    let tags = [("a", 1), ("b", 2), ("c", 3)].into();
+    spread_fields(&labels, tags).unwrap();
+    let span = finished_span("spread_fields");
+    assert_eq!(attribute(&span, "labels.env"), Some(Value::from("prod")));
+    assert_eq!(attribute(&span, "labels.team"), Some(Value::from("core")));
+    assert_eq!(attribute(&span, "labels"), None);
+    // Capped at two entries
+    assert_eq!(attribute(&span, "tags.a"), Some(Value::I64(1)));
+    assert_eq!(attribute(&span, "tags.b"), Some(Value::I64(2)));
+    assert_eq!(attribute(&span, "tags.c"), None);
+}
+
+#[derive(Debug)]
+enum Validation {
+    Rejected,
+}
+
+#[instrument(ret, err)]
+fn nested_result(outer_ok: bool) -> Result<Result<u32, Validation>, FetchError> {
+    if outer_ok {
+        Ok(Err(Validation::Rejected))
+    } else {
+        Err(FetchError::Timeout)
+    }
+}
+
+#[test]
+fn test_nested_result() {
+    exporter();
+    nested_result(true).unwrap().unwrap_err();
+    nested_result(false).unwrap_err();
+    let spans = exporter().get_finished_spans().unwrap();
+    let spans: Vec<_> = spans
+        .iter()
+        .filter(|span| span.name == "nested_result")
+        .collect();
+
+    // An inner `Err` is a successful call, recorded as is
+    assert_eq!(
+        attribute(spans[0], "return"),
+        Some(Value::from("Err(Rejected)"))
+    );
+    assert_eq!(spans[0].status, Status::Ok);
+    assert!(spans[0].events.is_empty());
+
+    assert_eq!(attribute(spans[1], "return"), None);
+    assert_eq!(spans[1].status, Status::error("Timeout"));
+}
+
+#[instrument(ret)]
+fn body_status(items: u32) -> Result<u32, String> {
+    if items > 1 {
+        set_span_status!(Status::error("partially synced"));
+    }
+    Ok(items / 2)
+}
+
+#[instrument(err)]
+async fn body_status_async() -> Result<(), FetchError> {
+    tokio::task::yield_now().await;
+    set_span_status!(Status::Unset);
+    Err(FetchError::NotFound)
+}
+
+#[tokio::test]
+async fn test_set_span_status() {
+    exporter();
+    body_status(3).unwrap();
+    // Spawned to check the future is still `Send`
+    tokio::spawn(body_status_async())
+        .await
+        .unwrap()
+        .unwrap_err();
+
+    let span = finished_span("body_status");
+    assert_eq!(span.status, Status::error("partially synced"));
+    assert_eq!(attribute(&span, "return"), Some(Value::from("1")));
+    // Error details are still recorded, only the status is left to the body
+    let span = finished_span("body_status_async");
+    assert_eq!(span.status, Status::Unset);
+    assert_eq!(attribute(&span, "error"), Some(Value::from("NotFound")));
+}
+
+async fn fetch_remote(fail: bool) -> Result<u32, FetchError> {
+    tokio::task::yield_now().await;
+    if fail {
+        Err(FetchError::Timeout)
+    } else {
+        Ok(21)
+    }
+}
+
+#[instrument(ret, err)]
+async fn await_question_mark(fail: bool) -> Result<u32, FetchError> {
+    let value = fetch_remote(fail).await?;
+    Ok(value * 2)
+}
+
+#[tokio::test]
+async fn test_await_question_mark() {
+    exporter();
+    assert_eq!(await_question_mark(false).await.unwrap(), 42);
+    assert!(matches!(
+        await_question_mark(true).await,
+        Err(FetchError::Timeout)
+    ));
+    let spans = exporter().get_finished_spans().unwrap();
+    let spans: Vec<_> = spans
+        .iter()
+        .filter(|span| span.name == "await_question_mark")
+        .collect();
+    assert_eq!(spans.len(), 2);
+    assert_eq!(attribute(spans[0], "return"), Some(Value::from("42")));
+    assert_eq!(spans[0].status, Status::Ok);
+    // The error propagated by `?` is seen by the error capture
+    assert_eq!(attribute(spans[1], "error"), Some(Value::from("Timeout")));
+    assert_eq!(spans[1].status, Status::error("Timeout"));
+    assert_eq!(spans[1].events.len(), 1);
+}
+
+#[instrument(record_visibility)]
+pub fn visibility_pub() -> Result<(), String> {
+    Ok(())
+}
+
+#[instrument(record_visibility)]
+pub(crate) fn visibility_crate() -> Result<(), String> {
+    Ok(())
+}
+
+#[instrument(record_visibility)]
+fn visibility_private() -> Result<(), String> {
+    Ok(())
+}
+
+#[test]
+fn test_record_visibility() {
+    exporter();
+    visibility_pub().unwrap();
+    visibility_crate().unwrap();
+    visibility_private().unwrap();
+    for (name, visibility) in [
+        ("visibility_pub", "pub"),
+        ("visibility_crate", "pub(crate)"),
+        ("visibility_private", "private"),
+    ] {
+        let span = finished_span(name);
+        assert_eq!(
+            attribute(&span, "code.visibility"),
+            Some(Value::from(visibility))
+        );
+    }
+}
+
+struct Callbacks<F: Fn(&str) -> Result<usize, FetchError>> {
+    on_lookup: F,
+}
+
+#[test]
+fn test_instrument_closure() {
+    exporter();
+    let mut calls = 0;
+    let mut on_event = instrument_closure!(
+        "closure_on_event",
+        |id: u32, secret: &str| {
+            let _ = secret;
+            calls += 1;
+            Ok::<_, String>(id)
+        },
+        skip(secret),
+        fields(kind = "event")
+    );
+    for id in 0..3 {
+        assert_eq!(on_event(id, "hunter2"), Ok(id));
+    }
+    assert_eq!(calls, 3);
+
+    let callbacks = Callbacks {
+        on_lookup: instrument_closure!(
+            "closure_on_lookup",
+            |key| if key.is_empty() {
+                Err(FetchError::NotFound)
+            } else {
+                Ok(key.len())
+            },
+            err
+        ),
+    };
+    assert_eq!((callbacks.on_lookup)("abc").unwrap(), 3);
+    (callbacks.on_lookup)("").unwrap_err();
+
+    let spans = exporter().get_finished_spans().unwrap();
+    let events: Vec<_> = spans
+        .iter()
+        .filter(|span| span.name == "closure_on_event")
+        .collect();
+    assert_eq!(events.len(), 3);
+    for (id, span) in events.iter().enumerate() {
+        assert_eq!(attribute(span, "id"), Some(Value::I64(id as i64)));
+        assert_eq!(attribute(span, "kind"), Some(Value::from("event")));
+        assert_eq!(attribute(span, "secret"), None);
+        assert_eq!(span.status, Status::Ok);
+    }
+
+    let lookups: Vec<_> = spans
+        .iter()
+        .filter(|span| span.name == "closure_on_lookup")
+        .collect();
+    assert_eq!(lookups.len(), 2);
+    assert_eq!(attribute(lookups[0], "key"), Some(Value::from("abc")));
+    assert_eq!(lookups[0].status, Status::Ok);
+    assert_eq!(lookups[1].status, Status::error("NotFound"));
+}
+
+#[derive(Debug, thiserror::Error, otel_instrument::VariantName)]
+enum StoreError {
+    #[error("timed out after {0}ms")]
+    Timeout(u64),
+    #[error("conflict on {key}")]
+    Conflict { key: String },
+}
+
+#[instrument(err(variant))]
+fn err_variant(timeout: bool) -> Result<(), StoreError> {
+    if timeout {
+        Err(StoreError::Timeout(250))
+    } else {
+        Err(StoreError::Conflict {
+            key: "user:7".into(),
+        })
+    }
+}
+
+#[test]
+fn test_err_variant() {
+    exporter();
+    err_variant(true).unwrap_err();
+    err_variant(false).unwrap_err();
+    let spans = exporter().get_finished_spans().unwrap();
+    let spans: Vec<_> = spans
+        .iter()
+        .filter(|span| span.name == "err_variant")
+        .collect();
+    assert_eq!(
+        attribute(spans[0], "error.variant"),
+        Some(Value::from("Timeout"))
+    );
+    assert_eq!(
+        attribute(spans[1], "error.variant"),
+        Some(Value::from("Conflict"))
+    );
+    // The full error is still recorded next to it
+    assert_eq!(
+        attribute(spans[1], "error"),
+        Some(Value::from("Conflict { key: \"user:7\" }"))
+    );
+}
+
+#[instrument(fields(
+    attempt,
+    outcome = if outcome.is_ok() { "ok" } else { "err" },
+    rows = try(outcome.as_ref().map(|rows| rows.len())),
+))]
+async fn outcome_fields(attempt: u32) -> Result<Vec<u32>, String> {
+    if attempt > 1 {
+        Ok(vec![1, 2, 3])
+    } else {
+        Err("busy".to_string())
+    }
+}
+
+#[tokio::test]
+async fn test_outcome_fields() {
+    exporter();
+    outcome_fields(2).await.unwrap();
+    outcome_fields(1).await.unwrap_err();
+    let spans = exporter().get_finished_spans().unwrap();
+    let spans: Vec<_> = spans
+        .iter()
+        .filter(|span| span.name == "outcome_fields")
+        .collect();
+    assert_eq!(attribute(spans[0], "attempt"), Some(Value::I64(2)));
+    assert_eq!(attribute(spans[0], "outcome"), Some(Value::from("ok")));
+    assert_eq!(attribute(spans[0], "rows"), Some(Value::from("3")));
+    assert_eq!(attribute(spans[1], "outcome"), Some(Value::from("err")));
+    assert_eq!(attribute(spans[1], "rows"), Some(Value::from("<err>")));
+}
+
+struct Job {
+    outcome: &'static str,
+}
+
+impl Job {
+    // Only a bare `outcome` is the result, so a field of the same name is recorded
+    // up front like any other field
+    #[instrument(fields(previous = self.outcome))]
+    fn finish_job(&mut self) -> Result<(), String> {
+        self.outcome = "done";
+        Ok(())
+    }
+}
+
+#[test]
+fn test_outcome_field_access() {
+    exporter();
+    let mut job = Job { outcome: "pending" };
+    job.finish_job().unwrap();
+    assert_eq!(job.outcome, "done");
+    let span = finished_span("finish_job");
+    assert_eq!(attribute(&span, "previous"), Some(Value::from("pending")));
+}
+
+#[derive(Debug, serde::Serialize)]
+struct Order {
+    id: u64,
+}
+
+#[instrument(fields(size = std::mem::size_of_val(value)))]
+async fn generic_borrowed<T: serde::Serialize + std::fmt::Debug>(
+    value: &T,
+) -> Result<String, String> {
+    tokio::task::yield_now().await;
+    let span_id = Context::current().span().span_context().span_id();
+    let body = serde_json::to_string(value).map_err(|e| e.to_string())?;
+    Ok(format!("{span_id}:{body}"))
+}
+
+#[tokio::test]
+async fn test_generic_borrowed() {
+    exporter();
+    // Borrowed from a local, so the future must not require `'static`
+    let order = Order { id: 7 };
+    let output = generic_borrowed::<Order>(&order).await.unwrap();
+    let span = finished_span("generic_borrowed");
+    assert_eq!(
+        output,
+        format!("{}:{{\"id\":7}}", span.span_context.span_id())
+    );
+    assert_eq!(
+        attribute(&span, "value"),
+        Some(Value::from("Order { id: 7 }"))
+    );
+    assert_eq!(attribute(&span, "size"), Some(Value::from("8")));
+}
+
+#[instrument(latency_bucket)]
+fn latency_bucket_default() -> Result<(), String> {
+    std::thread::sleep(std::time::Duration::from_millis(30));
+    Ok(())
+}
+
+#[instrument(latency_bucket(5, 20))]
+async fn latency_bucket_custom(sleep_ms: u64) -> Result<(), String> {
+    std::thread::sleep(std::time::Duration::from_millis(sleep_ms));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_latency_bucket() {
+    exporter();
+    latency_bucket_default().unwrap();
+    latency_bucket_custom(0).await.unwrap();
+    latency_bucket_custom(30).await.unwrap();
+
+    let span = finished_span("latency_bucket_default");
+    assert_eq!(
+        attribute(&span, "latency.bucket"),
+        Some(Value::from("10-100ms"))
+    );
+    let spans = exporter().get_finished_spans().unwrap();
+    let buckets: Vec<_> = spans
+        .iter()
+        .filter(|span| span.name == "latency_bucket_custom")
+        .map(|span| attribute(span, "latency.bucket"))
+        .collect();
+    assert_eq!(
+        buckets,
+        [Some(Value::from("<5ms")), Some(Value::from(">=20ms"))]
+    );
+}
+
+#[derive(Debug, thiserror::Error)]
+enum ServiceError {
+    #[error("store failed")]
+    Store(#[from] StoreError),
+}
+
+fn store_lookup() -> Result<u32, StoreError> {
+    Err(StoreError::Timeout(250))
+}
+
+#[instrument(err)]
+fn converted_error(question_mark: bool) -> Result<u32, ServiceError> {
+    if question_mark {
+        // Converted by `?` through the `#[from]` impl
+        return Ok(store_lookup()?);
+    }
+    Err(StoreError::Conflict {
+        key: "user:7".into(),
+    }
+    .into())
+}
+
+#[test]
+fn test_converted_error() {
+    exporter();
+    converted_error(true).unwrap_err();
+    converted_error(false).unwrap_err();
+    let spans = exporter().get_finished_spans().unwrap();
+    let spans: Vec<_> = spans
+        .iter()
+        .filter(|span| span.name == "converted_error")
+        .collect();
+
+    // The converted error is recorded, not the one the body started with
+    assert_eq!(
+        attribute(spans[0], "error"),
+        Some(Value::from("Store(Timeout(250))"))
+    );
+    assert_eq!(spans[0].status, Status::error("Store(Timeout(250))"));
+    let exception = &spans[0].events[0];
+    assert_eq!(
+        exception
+            .attributes
+            .iter()
+            .find(|kv| kv.key.as_str() == "exception.message")
+            .map(|kv| kv.value.clone()),
+        Some(Value::from("store failed"))
+    );
+    assert_eq!(
+        attribute(spans[1], "error"),
+        Some(Value::from("Store(Conflict { key: \"user:7\" })"))
+    );
+}
+
+struct Permit;
+
+// Stands in for a rate limiter that is slow to hand out permits
+async fn delayed_permit(delay_ms: u64) -> Permit {
+    tokio::task::yield_now().await;
+    std::thread::sleep(std::time::Duration::from_millis(delay_ms));
+    Permit
+}
+
+#[instrument(gate = delayed_permit(30), slo_ms = 20)]
+async fn gated(id: u32) -> Result<u32, String> {
+    Ok(id)
+}
+
+#[tokio::test]
+async fn test_gate() {
+    exporter();
+    assert_eq!(gated(3).await, Ok(3));
+    let span = finished_span("gated");
+    let Some(Value::F64(wait_ms)) = attribute(&span, "wait_ms") else {
+        panic!("`wait_ms` was not recorded");
+    };
+    assert!(wait_ms >= 30.0);
+    // The wait isn't part of the body's own timing
+    assert_eq!(attribute(&span, "slo.exceeded"), Some(Value::Bool(false)));
+    assert_eq!(attribute(&span, "id"), Some(Value::I64(3)));
+}
+
+fn format_timestamp(ts: &std::time::Duration) -> String {
+    format!("T+{}.{:03}s", ts.as_secs(), ts.subsec_millis())
+}
+
+#[instrument(skip(ts), fields(ts = fmt(format_timestamp, ts), upper = fmt(str::to_uppercase, name)))]
+fn custom_formatter(ts: std::time::Duration, name: &str) -> Result<(), String> {
+    Ok(())
+}
+
+#[test]
+fn test_custom_formatter() {
+    exporter();
+    custom_formatter(std::time::Duration::from_millis(12_345), "jane").unwrap();
+    let span = finished_span("custom_formatter");
+    assert_eq!(attribute(&span, "ts"), Some(Value::from("T+12.345s")));
+    assert_eq!(attribute(&span, "upper"), Some(Value::from("JANE")));
+}
+
+fn format_total(total: &u32) -> String {
+    format!("{total} items")
+}
+
+#[instrument(fields(total = fmt(format_total, a + b)))]
+fn custom_formatter_expression(a: u32, b: u32) -> Result<(), String> {
+    Ok(())
+}
+
+#[test]
+fn test_custom_formatter_expression() {
+    exporter();
+    custom_formatter_expression(2, 3).unwrap();
+    let span = finished_span("custom_formatter_expression");
+    // The whole expression is borrowed, not just its first operand
+    assert_eq!(attribute(&span, "total"), Some(Value::from("5 items")));
+}
+
+// Deliberately without `Debug`, which is only needed to record error details
+struct Opaque;
+
+#[instrument]
+fn default_error_status(fail: bool) -> Result<u32, Opaque> {
+    if fail { Err(Opaque) } else { Ok(1) }
+}
+
+#[test]
+fn test_default_error_status() {
+    exporter();
+    assert!(default_error_status(false).is_ok());
+    assert!(default_error_status(true).is_err());
+    let spans = exporter().get_finished_spans().unwrap();
+    let spans: Vec<_> = spans
+        .iter()
+        .filter(|span| span.name == "default_error_status")
+        .collect();
+    assert_eq!(spans[0].status, Status::Ok);
+    // Failures are visible without `err`, described by the error type only
+    assert_eq!(spans[1].status, Status::error("span_attributes::Opaque"));
+    assert_eq!(attribute(spans[1], "error"), None);
+    assert!(spans[1].events.is_empty());
+}
+
+// `#[instrument]` goes above the body-rewriting macro, which then boxes the
+// instrumented function, so every level of the recursion gets its own span
+#[instrument(ret)]
+#[async_recursion::async_recursion]
+async fn recursive_sum(depth: u32) -> Result<u32, String> {
+    if depth == 0 {
+        return Ok(0);
+    }
+    Ok(depth + recursive_sum(depth - 1).await?)
+}
+
+#[tokio::test]
+async fn test_async_recursion() {
+    exporter();
+    // Boxed as `Send`, so the recursion can be spawned
+    assert_eq!(tokio::spawn(recursive_sum(3)).await.unwrap(), Ok(6));
+    let spans = exporter().get_finished_spans().unwrap();
+    let spans: Vec<_> = spans
+        .iter()
+        .filter(|span| span.name == "recursive_sum")
+        .collect();
+    // Inner levels end first, each a child of the level above
+    let depths: Vec<_> = spans.iter().map(|span| attribute(span, "depth")).collect();
+    assert_eq!(
+        depths,
+        (0..=3)
+            .map(|depth| Some(Value::I64(depth)))
+            .collect::<Vec<_>>()
+    );
+    for pair in spans.windows(2) {
+        assert_eq!(pair[0].parent_span_id, pair[1].span_context.span_id());
+    }
+    assert_eq!(attribute(spans[3], "return"), Some(Value::from("6")));
+}
+
+// Items of the test crate named like the helpers of `tracer_name!` don't collide
+// with them
+#[derive(Debug)]
+struct Traceable;
+
+fn traceparent() -> &'static str {
+    "user"
+}
+
+mod tracer_name_import {
+    // Only the constant is imported, which brings the generated items along
+    use crate::_OTEL_TRACER_NAME;
+    use crate::{attribute, exporter, finished_span};
+    use opentelemetry::{Context, Value};
+    use otel_instrument::instrument;
+
+    #[instrument(fields(kind = "import"), parent = Context::current())]
+    fn submodule_import(count: u32) -> Result<(), String> {
+        Ok(())
+    }
+
+    #[test]
+    fn test_submodule_import() {
+        exporter();
+        submodule_import(3).unwrap();
+        let span = finished_span("submodule_import");
+        assert_eq!(attribute(&span, "count"), Some(Value::I64(3)));
+        assert_eq!(attribute(&span, "kind"), Some(Value::from("import")));
+        assert_eq!(super::traceparent(), "user");
+        assert_eq!(format!("{:?}", super::Traceable), "Traceable");
+    }
+}
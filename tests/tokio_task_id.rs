@@ -0,0 +1,42 @@
+#![cfg(feature = "tokio")]
+
+use opentelemetry::global;
+use opentelemetry_sdk::trace::{InMemorySpanExporter, SdkTracerProvider};
+use otel_instrument::{instrument, tracer_name};
+
+tracer_name!("otel-instrument-tokio-tests");
+
+#[instrument(task_id)]
+async fn spawned_work() -> Result<(), String> {
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_task_id() {
+    let exporter = InMemorySpanExporter::default();
+    let tracer_provider = SdkTracerProvider::builder()
+        .with_simple_exporter(exporter.clone())
+        .build();
+    global::set_tracer_provider(tracer_provider);
+
+    let handle = tokio::spawn(spawned_work());
+    let task_id = handle.id();
+    handle.await.unwrap().unwrap();
+
+    // Outside of a spawned task the attribute is omitted
+    futures_util::FutureExt::now_or_never(spawned_work())
+        .unwrap()
+        .unwrap();
+
+    let spans = exporter.get_finished_spans().unwrap();
+    let task_ids: Vec<_> = spans
+        .iter()
+        .map(|span| {
+            span.attributes
+                .iter()
+                .find(|kv| kv.key.as_str() == "tokio.task.id")
+                .map(|kv| kv.value.to_string())
+        })
+        .collect();
+    assert_eq!(task_ids, [Some(task_id.to_string()), None]);
+}
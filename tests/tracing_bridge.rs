@@ -0,0 +1,141 @@
+#![cfg(feature = "tracing-bridge")]
+
+use otel_instrument::{instrument, tracer_name};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Metadata, Subscriber};
+
+tracer_name!("otel-instrument-tracing-tests");
+
+// Minimal subscriber capturing every new span with its fields, and every span
+// entered, so the mirrored spans can be asserted
+struct CaptureSubscriber;
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+type Fields = Vec<(String, String)>;
+
+static SPANS: Mutex<Vec<(String, Fields)>> = Mutex::new(Vec::new());
+static ENTERED: Mutex<Vec<u64>> = Mutex::new(Vec::new());
+
+struct FieldVisitor(Fields);
+
+impl Visit for FieldVisitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        self.0
+            .push((field.name().to_string(), format!("{value:?}")));
+    }
+}
+
+impl Subscriber for CaptureSubscriber {
+    fn enabled(&self, _metadata: &Metadata<'_>) -> bool {
+        true
+    }
+
+    fn new_span(&self, span: &Attributes<'_>) -> Id {
+        let mut visitor = FieldVisitor(Vec::new());
+        span.record(&mut visitor);
+        SPANS
+            .lock()
+            .unwrap()
+            .push((span.metadata().name().to_string(), visitor.0));
+        Id::from_u64(NEXT_ID.fetch_add(1, Ordering::SeqCst))
+    }
+
+    fn record(&self, _span: &Id, _values: &Record<'_>) {}
+
+    fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+
+    fn event(&self, _event: &Event<'_>) {}
+
+    fn enter(&self, span: &Id) {
+        ENTERED.lock().unwrap().push(span.into_u64());
+    }
+
+    fn exit(&self, _span: &Id) {}
+}
+
+#[instrument(skip(secret), fields(region = "eu"))]
+fn bridged_sync(user: &str, secret: &str) -> Result<(), String> {
+    let _ = secret;
+    Ok(())
+}
+
+#[instrument]
+async fn bridged_async(count: u32) -> Result<(), String> {
+    Ok(())
+}
+
+// Mirrored even when the OpenTelemetry span is skipped for being too fast
+#[instrument(min_duration_ms = 60_000)]
+fn bridged_deferred(id: u32) -> Result<(), String> {
+    Ok(())
+}
+
+#[instrument(min_duration_ms = 60_000)]
+async fn bridged_deferred_async(id: u32) -> Result<(), String> {
+    Ok(())
+}
+
+// Entered on every poll of the returned stream
+#[instrument(stream)]
+fn bridged_stream(count: u32) -> impl futures_util::Stream<Item = u32> {
+    futures_util::stream::iter(0..count)
+}
+
+#[tokio::test]
+async fn test_tracing_bridge() {
+    tracing::subscriber::set_global_default(CaptureSubscriber).unwrap();
+
+    bridged_sync("jane", "hunter2").unwrap();
+    bridged_async(3).await.unwrap();
+    bridged_deferred(4).unwrap();
+    bridged_deferred_async(5).await.unwrap();
+    let items: Vec<_> = futures_util::StreamExt::collect(bridged_stream(2)).await;
+    assert_eq!(items, [0, 1]);
+
+    let spans = SPANS.lock().unwrap().clone();
+    assert_eq!(
+        spans,
+        [
+            (
+                "bridged_sync".to_string(),
+                vec![
+                    ("user".to_string(), "jane".to_string()),
+                    ("region".to_string(), "eu".to_string()),
+                ]
+            ),
+            (
+                "bridged_async".to_string(),
+                vec![("count".to_string(), "3".to_string())]
+            ),
+            (
+                "bridged_deferred".to_string(),
+                vec![("id".to_string(), "4".to_string())]
+            ),
+            (
+                "bridged_deferred_async".to_string(),
+                vec![("id".to_string(), "5".to_string())]
+            ),
+            (
+                "bridged_stream".to_string(),
+                vec![("count".to_string(), "2".to_string())]
+            ),
+        ]
+    );
+    // Every span is entered while the function body runs
+    let entered = ENTERED.lock().unwrap().clone();
+    for id in 1..=4 {
+        assert!(entered.contains(&id));
+    }
+    // The stream span is entered when the stream is created, then on each of its
+    // three polls
+    assert_eq!(entered.iter().filter(|id| **id == 5).count(), 4);
+}
+
+#[test]
+fn ui() {
+    let cases = trybuild::TestCases::new();
+    cases.compile_fail("tests/ui/tracing_bridge/*.rs");
+}
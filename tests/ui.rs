@@ -0,0 +1,14 @@
+// Compile errors are checked against the `.stderr` snapshots next to each case
+#[test]
+fn ui() {
+    // Cargo passes the build script's variables on to the tests, while the cases are
+    // built like a crate without them
+    for var in ["VERGEN_GIT_SHA", "VERGEN_BUILD_TIMESTAMP"] {
+        // SAFETY: this is the only test in the binary, so nothing reads the environment
+        // concurrently
+        unsafe { std::env::remove_var(var) };
+    }
+    let cases = trybuild::TestCases::new();
+    cases.compile_fail("tests/ui/*.rs");
+    cases.pass("tests/ui/pass/*.rs");
+}
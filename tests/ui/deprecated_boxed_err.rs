@@ -0,0 +1,22 @@
+// Denied, so the deprecation warning of the legacy form shows as an error
+#![deny(deprecated)]
+
+use otel_instrument::{instrument, tracer_name};
+
+tracer_name!("ui-tests");
+
+// `e.as_ref()` is already the default for boxed errors
+#[instrument(err = e.as_ref())]
+fn legacy() -> Result<(), Box<dyn std::error::Error>> {
+    Ok(())
+}
+
+// Still fine where it isn't the default, like through an alias
+type BoxResult<T> = Result<T, Box<dyn std::error::Error>>;
+
+#[instrument(err = e.as_ref())]
+fn aliased() -> BoxResult<()> {
+    Ok(())
+}
+
+fn main() {}
@@ -0,0 +1,33 @@
+use otel_instrument::{instrument, tracer_name};
+
+tracer_name!("ui-tests");
+
+#[instrument(name = "first", name = "second")]
+fn duplicate_name() -> Result<(), String> {
+    Ok(())
+}
+
+#[instrument(
+    parent = opentelemetry::Context::current(),
+    parent = opentelemetry::Context::new()
+)]
+fn duplicate_parent() -> Result<(), String> {
+    Ok(())
+}
+
+#[instrument(err, err)]
+fn duplicate_err() -> Result<(), String> {
+    Ok(())
+}
+
+#[instrument(ret, ret)]
+fn duplicate_ret() -> Result<u32, String> {
+    Ok(1)
+}
+
+#[instrument(skip_all, skip_all)]
+fn duplicate_skip_all(id: u32) -> Result<u32, String> {
+    Ok(id)
+}
+
+fn main() {}
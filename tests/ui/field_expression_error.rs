@@ -0,0 +1,18 @@
+use otel_instrument::{instrument, tracer_name};
+
+tracer_name!("ui-tests");
+
+struct Session;
+
+// Neither `SpanAttributeValue` nor `Debug`, so the errors point at the user's tokens
+#[instrument(fields(session = Session))]
+fn login() -> Result<(), String> {
+    Ok(())
+}
+
+#[instrument]
+fn resume(session: Session) -> Result<(), String> {
+    Ok(())
+}
+
+fn main() {}
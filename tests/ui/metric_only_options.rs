@@ -0,0 +1,60 @@
+use otel_instrument::{instrument, tracer_name};
+
+tracer_name!("ui-tests");
+
+// Options recording on the span are reported on the option itself
+#[instrument(metric_only, expose_ids = out)]
+fn expose(out: &mut Option<opentelemetry::trace::SpanContext>) -> Result<(), String> {
+    Ok(())
+}
+
+#[instrument(metric_only, request_id = id)]
+fn request(id: u64) -> Result<(), String> {
+    Ok(())
+}
+
+#[instrument(metric_only, task_local)]
+async fn task_local() -> Result<(), String> {
+    Ok(())
+}
+
+#[instrument(metric_only, strict)]
+fn strict(id: u64) -> Result<(), String> {
+    Ok(())
+}
+
+#[instrument(metric_only, skip(id))]
+fn skip(id: u64) -> Result<(), String> {
+    Ok(())
+}
+
+#[instrument(metric_only, build_info)]
+fn build_info() -> Result<(), String> {
+    Ok(())
+}
+
+#[instrument(metric_only, status = manual)]
+fn manual() -> Result<(), String> {
+    Ok(())
+}
+
+// Macros acting on the active span would record on the caller's span
+#[instrument(metric_only)]
+fn checkpoints() -> Result<(), String> {
+    otel_instrument::checkpoint!("loaded");
+    Ok(())
+}
+
+#[instrument(metric_only)]
+fn status() -> Result<(), String> {
+    otel_instrument::set_span_status!(opentelemetry::trace::Status::Ok);
+    Ok(())
+}
+
+// Naming the function and its scope is still supported
+#[instrument(metric_only, name = "named", qualified_name)]
+fn named() -> Result<(), String> {
+    Ok(())
+}
+
+fn main() {}
@@ -0,0 +1,11 @@
+use otel_instrument::{instrument, tracer_name};
+
+tracer_name!("ui-tests");
+
+// The parameter would be shadowed by the result in the post-call field
+#[instrument(fields(ok = outcome.is_ok()))]
+fn settle(outcome: bool) -> Result<(), String> {
+    if outcome { Ok(()) } else { Err("failed".to_string()) }
+}
+
+fn main() {}
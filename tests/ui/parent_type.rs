@@ -0,0 +1,11 @@
+use otel_instrument::{instrument, tracer_name};
+
+tracer_name!("ui-tests");
+
+// A header value has to be parsed with `_otel::traceparent` first
+#[instrument(skip(header), parent = header)]
+fn consume(header: &str) -> Result<(), String> {
+    Ok(())
+}
+
+fn main() {}
@@ -0,0 +1,28 @@
+use opentelemetry_sdk::trace::{InMemorySpanExporter, SdkTracerProvider};
+use otel_instrument::{instrument, tracer_name};
+
+tracer_name!("ui-tests");
+
+#[instrument(build_info)]
+fn build() -> Result<(), String> {
+    Ok(())
+}
+
+// Built outside of the crate's build script, without the `VERGEN_*` variables, so
+// both attributes are omitted
+fn main() {
+    let exporter = InMemorySpanExporter::default();
+    let provider = SdkTracerProvider::builder()
+        .with_simple_exporter(exporter.clone())
+        .build();
+    opentelemetry::global::set_tracer_provider(provider);
+
+    build().unwrap();
+    let spans = exporter.get_finished_spans().unwrap();
+    let span = spans.iter().find(|span| span.name == "build").unwrap();
+    assert!(
+        span.attributes
+            .iter()
+            .all(|attribute| !attribute.key.as_str().starts_with("build."))
+    );
+}
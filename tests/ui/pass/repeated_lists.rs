@@ -0,0 +1,13 @@
+use otel_instrument::{instrument, tracer_name};
+
+tracer_name!("ui-tests");
+
+// `skip(...)` and `fields(...)` accumulate when given more than once
+#[instrument(skip(a), fields(x = 1), skip(b), fields(y = 2))]
+fn repeated_lists(a: u32, b: u32, c: u32) -> Result<u32, String> {
+    Ok(a + b + c)
+}
+
+fn main() {
+    assert_eq!(repeated_lists(1, 2, 3), Ok(6));
+}
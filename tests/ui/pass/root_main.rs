@@ -0,0 +1,36 @@
+use opentelemetry_sdk::trace::{InMemorySpanExporter, SdkTracerProvider};
+use otel_instrument::{instrument, tracer_name};
+
+tracer_name!("ui-tests");
+
+// The provider of a program, checking on shutdown that the span of `main` was
+// exported before the process exits
+struct Tracing {
+    provider: SdkTracerProvider,
+    exporter: InMemorySpanExporter,
+}
+
+impl Tracing {
+    fn shutdown(&self) -> Result<(), String> {
+        self.provider.shutdown().map_err(|e| e.to_string())?;
+        let spans = self.exporter.get_finished_spans().unwrap();
+        assert!(spans.iter().any(|span| span.name == "main"));
+        Ok(())
+    }
+}
+
+fn init_tracing() -> Tracing {
+    let exporter = InMemorySpanExporter::default();
+    let provider = SdkTracerProvider::builder()
+        .with_batch_exporter(exporter.clone())
+        .build();
+    opentelemetry::global::set_tracer_provider(provider.clone());
+    Tracing { provider, exporter }
+}
+
+#[tokio::main(flavor = "current_thread")]
+#[instrument(root = init_tracing())]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    tokio::task::yield_now().await;
+    Ok(())
+}
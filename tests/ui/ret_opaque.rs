@@ -0,0 +1,19 @@
+// Denied, so the warning about the skipped success value shows as an error
+#![deny(deprecated)]
+
+use otel_instrument::{instrument, tracer_name};
+
+tracer_name!("ui-tests");
+
+#[instrument(ret)]
+fn numbers() -> Result<impl Iterator<Item = u32>, String> {
+    Ok(0..3)
+}
+
+// Formatting bounds are used instead
+#[instrument(ret)]
+fn message() -> Result<impl std::fmt::Display, String> {
+    Ok("ready")
+}
+
+fn main() {}
@@ -0,0 +1,12 @@
+use otel_instrument::{instrument, tracer_name};
+
+tracer_name!("ui-tests");
+
+// The mirrored span is attached by a wrapper implementing the returned `Stream`
+// trait, which a boxed stream doesn't name
+#[instrument(stream)]
+fn numbers() -> std::pin::Pin<Box<dyn futures_util::Stream<Item = u32>>> {
+    Box::pin(futures_util::stream::iter([1, 2, 3]))
+}
+
+fn main() {}
@@ -0,0 +1,13 @@
+#![deny(unused_variables)]
+
+use otel_instrument::{instrument, tracer_name};
+
+tracer_name!("ui-tests");
+
+// `id` is recorded on the span, while the skipped `token` is read by nothing
+#[instrument(skip(token))]
+fn lookup(id: u64, token: &str) -> Result<(), String> {
+    Ok(())
+}
+
+fn main() {}